@@ -1,4 +1,4 @@
-use std::{env, sync::Arc};
+use std::{env, sync::Arc, time::Duration};
 
 use langchain::ReactAgent;
 use langchain_core::message::Message;
@@ -9,9 +9,10 @@ use qq_bot::{
     event_client::QQEvent,
     models::{
         client_error::ClientError,
+        handler_error::HandlerError,
         message::{C2CMessage, GroupMessage, PostMessageBody},
     },
-    services::{client::QQClient, server::ServerBuilder},
+    services::{client::QQClient, dispatcher::Event, middleware::Timeout, server::ServerBuilder},
 };
 use tracing::debug;
 use tracing_subscriber::EnvFilter;
@@ -70,6 +71,12 @@ impl QQEvent for Handler {
             .await?;
         Ok(())
     }
+
+    async fn on_error(&self, event: &Event, _error: &HandlerError, client: &QQClient) {
+        if let Some(message) = event.message() {
+            let _ = client.reply(&message, "出了点问题，请稍后再试。").await;
+        }
+    }
 }
 
 #[tokio::main]
@@ -82,7 +89,7 @@ async fn main() {
         .pretty()
         .init();
 
-    let config = Config::from_env();
+    let config = Config::load().expect("加载配置失败");
 
     let api_key = env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set");
     let model = ChatOpenAIBuilder::from_base(MODEL, BASE_URL, api_key.as_str()).build();
@@ -125,8 +132,11 @@ async fn main() {
 
     // Example： Use default handler
     ServerBuilder::new(config)
+        .with_middleware(
+            Timeout::new(Duration::from_secs(120)).with_fallback_reply("想得有点久，稍后再问我。"),
+        )
         .with_event_handler(Handler { agent })
-        .start("0.0.0.0:8080")
+        .run()
         .await
         .unwrap();
 }
@@ -0,0 +1,205 @@
+use std::str::FromStr;
+
+use crate::models::command_error::ArgError;
+
+/// 命令参数，按空白切分，支持用单引号或双引号包含空格
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    raw: String,
+    /// 每个参数在 `raw` 中的起始位置和解析后的值
+    tokens: Vec<(usize, String)>,
+    /// 已经消费的参数个数
+    pos: usize,
+}
+
+impl Args {
+    pub fn parse(input: &str) -> Result<Self, ArgError> {
+        let mut tokens = Vec::new();
+        let mut chars = input.char_indices().peekable();
+        while let Some(&(start, c)) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+            let mut token = String::new();
+            let mut quote = None;
+            while let Some(&(_, c)) = chars.peek() {
+                match quote {
+                    Some(q) if c == q => quote = None,
+                    Some(_) => token.push(c),
+                    None if c == '"' || c == '\'' => quote = Some(c),
+                    None if c.is_whitespace() => break,
+                    None => token.push(c),
+                }
+                chars.next();
+            }
+            if quote.is_some() {
+                return Err(ArgError::UnclosedQuote);
+            }
+            tokens.push((start, token));
+        }
+        Ok(Self {
+            raw: input.to_string(),
+            tokens,
+            pos: 0,
+        })
+    }
+
+    /// 原始参数文本
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// 下一个参数，不消费
+    pub fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|(_, t)| t.as_str())
+    }
+
+    /// 消费下一个参数
+    pub fn next_token(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos)?.1.clone();
+        self.pos += 1;
+        Some(token)
+    }
+
+    /// 剩余未消费的参数个数
+    pub fn remaining(&self) -> usize {
+        self.tokens.len() - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// 已消费的参数个数，也是最近一个参数从 1 开始的序号
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// 消费剩余的全部参数，返回原始文本
+    pub fn rest(&mut self) -> String {
+        let Some(&(start, _)) = self.tokens.get(self.pos) else {
+            return String::new();
+        };
+        self.pos = self.tokens.len();
+        self.raw[start..].trim_end().to_string()
+    }
+
+    /// 按类型解析参数
+    pub fn parse_next<T: FromArgs>(&mut self) -> Result<T, ArgError> {
+        T::from_args(self)
+    }
+
+    /// 确认参数已全部消费
+    pub fn finish(&self) -> Result<(), ArgError> {
+        match self.peek() {
+            Some(token) => Err(ArgError::TooMany(token.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn parse_token<T: FromStr>(&mut self, expected: &'static str) -> Result<T, ArgError> {
+        let token = self.next_token().ok_or(ArgError::Missing(self.pos + 1))?;
+        token.parse().map_err(|_| ArgError::Invalid {
+            index: self.pos,
+            value: token,
+            expected,
+        })
+    }
+}
+
+/// 从命令参数中解析出的类型
+///
+/// `Option<T>` 在没有剩余参数时为 `None`，`Vec<T>` 消费剩余的全部参数，
+/// 元组按顺序解析每个元素。
+pub trait FromArgs: Sized {
+    fn from_args(args: &mut Args) -> Result<Self, ArgError>;
+}
+
+/// 剩余的全部参数原样拼接成的文本，例如 `/echo 你好 世界` 中的 `你好 世界`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rest(pub String);
+
+impl FromArgs for Rest {
+    fn from_args(args: &mut Args) -> Result<Self, ArgError> {
+        Ok(Rest(args.rest()))
+    }
+}
+
+impl FromArgs for String {
+    fn from_args(args: &mut Args) -> Result<Self, ArgError> {
+        args.next_token().ok_or(ArgError::Missing(args.pos + 1))
+    }
+}
+
+impl FromArgs for bool {
+    fn from_args(args: &mut Args) -> Result<Self, ArgError> {
+        let token = args.next_token().ok_or(ArgError::Missing(args.pos + 1))?;
+        match token.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(true),
+            "false" | "no" | "off" | "0" => Ok(false),
+            _ => Err(ArgError::Invalid {
+                index: args.pos,
+                value: token,
+                expected: "布尔值",
+            }),
+        }
+    }
+}
+
+macro_rules! impl_from_str {
+    ($expected:literal: $($ty:ty),*) => {
+        $(
+            impl FromArgs for $ty {
+                fn from_args(args: &mut Args) -> Result<Self, ArgError> {
+                    args.parse_token($expected)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_str!("整数": i8, i16, i32, i64, u8, u16, u32, u64, usize);
+impl_from_str!("数字": f32, f64);
+
+impl<T: FromArgs> FromArgs for Option<T> {
+    fn from_args(args: &mut Args) -> Result<Self, ArgError> {
+        if args.is_empty() {
+            Ok(None)
+        } else {
+            T::from_args(args).map(Some)
+        }
+    }
+}
+
+impl<T: FromArgs> FromArgs for Vec<T> {
+    fn from_args(args: &mut Args) -> Result<Self, ArgError> {
+        let mut values = Vec::new();
+        while !args.is_empty() {
+            values.push(T::from_args(args)?);
+        }
+        Ok(values)
+    }
+}
+
+impl FromArgs for () {
+    fn from_args(_args: &mut Args) -> Result<Self, ArgError> {
+        Ok(())
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: FromArgs),+> FromArgs for ($($name,)+) {
+            fn from_args(args: &mut Args) -> Result<Self, ArgError> {
+                Ok(($($name::from_args(args)?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
@@ -0,0 +1,7 @@
+mod args;
+pub(crate) mod router;
+#[cfg(test)]
+mod tests;
+
+pub use args::{Args, FromArgs, Rest};
+pub use router::{CommandContext, CommandRouter, CommandSpec};
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{debug, warn};
+
+use crate::{
+    commands::args::{Args, FromArgs},
+    event_client::QQEvent,
+    models::{
+        client_error::ClientError,
+        command_error::CommandError,
+        message::{C2CMessage, ChannelMessage, GroupMessage, IncomingMessage},
+    },
+    services::client::QQClient,
+};
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+type Handler =
+    Arc<dyn Fn(CommandContext, Args) -> BoxFuture<Result<(), CommandError>> + Send + Sync>;
+type Fallback = Arc<dyn Fn(CommandContext) -> BoxFuture<Result<(), CommandError>> + Send + Sync>;
+
+const DEFAULT_PREFIX: &str = "/";
+const HELP_NAMES: [&str; 2] = ["help", "帮助"];
+
+/// 命令的名称、别名和帮助信息
+#[derive(Debug, Clone)]
+pub struct CommandSpec {
+    name: String,
+    aliases: Vec<String>,
+    usage: String,
+    description: String,
+}
+
+impl CommandSpec {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            aliases: Vec::new(),
+            usage: String::new(),
+            description: String::new(),
+        }
+    }
+
+    pub fn with_aliases<I, S>(mut self, aliases: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.aliases.extend(aliases.into_iter().map(Into::into));
+        self
+    }
+
+    /// 参数说明，例如 `<city> [days]`
+    pub fn with_usage(mut self, usage: impl Into<String>) -> Self {
+        self.usage = usage.into();
+        self
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    /// 带前缀的用法，例如 `/weather <city> [days]`
+    fn usage_line(&self, prefix: &str) -> String {
+        if self.usage.is_empty() {
+            format!("{}{}", prefix, self.name)
+        } else {
+            format!("{}{} {}", prefix, self.name, self.usage)
+        }
+    }
+}
+
+/// 命令处理函数收到的上下文
+#[derive(Clone)]
+pub struct CommandContext {
+    message: IncomingMessage,
+    client: QQClient,
+    command: String,
+}
+
+impl CommandContext {
+    pub fn message(&self) -> &IncomingMessage {
+        &self.message
+    }
+
+    pub fn client(&self) -> &QQClient {
+        &self.client
+    }
+
+    /// 用户输入的命令名，不含前缀
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// 被动回复触发命令的消息
+    pub async fn reply(&self, content: impl Into<String>) -> Result<(), ClientError> {
+        self.client.reply(&self.message, content).await
+    }
+}
+
+struct Command {
+    spec: CommandSpec,
+    handler: Handler,
+}
+
+/// 命令路由，按前缀和命令名把消息分发给注册的处理函数
+///
+/// 实现了 [`QQEvent`]，群聊、单聊和频道消息使用同一套命令。消息开头的 `@机器人`
+/// 和空白会被忽略，命令名不区分大小写。参数按 [`FromArgs`] 解析，解析失败时回复
+/// 错误和用法；未注册的命令交给 [`with_fallback`](Self::with_fallback) 设置的处理函数。
+///
+/// ```ignore
+/// let router = CommandRouter::new().with_command(
+///     CommandSpec::new("weather")
+///         .with_aliases(["天气"])
+///         .with_usage("<city> [days]")
+///         .with_description("查询天气"),
+///     |ctx: CommandContext, (city, days): (String, Option<u32>)| async move {
+///         ctx.reply(format!("{} 未来 {} 天晴", city, days.unwrap_or(1))).await?;
+///         Ok(())
+///     },
+/// );
+/// ```
+#[derive(Clone)]
+pub struct CommandRouter {
+    prefix: String,
+    commands: Vec<Arc<Command>>,
+    /// 小写的命令名和别名到 `commands` 下标的映射
+    lookup: HashMap<String, usize>,
+    fallback: Option<Fallback>,
+    help: bool,
+}
+
+impl Default for CommandRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandRouter {
+    pub fn new() -> Self {
+        Self {
+            prefix: DEFAULT_PREFIX.to_string(),
+            commands: Vec::new(),
+            lookup: HashMap::new(),
+            fallback: None,
+            help: true,
+        }
+    }
+
+    /// 设置命令前缀，默认 `/`；设置为空字符串时每条消息都按命令解析
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// 注册命令，同名或同别名的命令以后注册的为准
+    pub fn with_command<A, F, Fut>(mut self, spec: CommandSpec, handler: F) -> Self
+    where
+        A: FromArgs + Send + 'static,
+        F: Fn(CommandContext, A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), CommandError>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let handler: Handler = Arc::new(move |ctx, mut args| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                let parsed = A::from_args(&mut args)?;
+                args.finish()?;
+                handler(ctx, parsed).await
+            })
+        });
+
+        let index = self.commands.len();
+        for name in std::iter::once(&spec.name).chain(&spec.aliases) {
+            self.lookup.insert(name.to_lowercase(), index);
+        }
+        self.commands.push(Arc::new(Command { spec, handler }));
+        self
+    }
+
+    /// 处理未注册的命令，未设置时回复提示
+    pub fn with_fallback<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(CommandContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), CommandError>> + Send + 'static,
+    {
+        self.fallback = Some(Arc::new(move |ctx| Box::pin(handler(ctx))));
+        self
+    }
+
+    /// 关闭内置的 `help` 命令
+    pub fn without_help(mut self) -> Self {
+        self.help = false;
+        self
+    }
+
+    /// 所有命令的帮助信息
+    pub fn help_text(&self) -> String {
+        let mut lines = vec!["可用命令:".to_string()];
+        for command in &self.commands {
+            lines.push(self.describe(&command.spec));
+        }
+        if self.help {
+            lines.push(format!("{}help [命令] - 查看帮助", self.prefix));
+        }
+        lines.join("\n")
+    }
+
+    fn describe(&self, spec: &CommandSpec) -> String {
+        let mut line = spec.usage_line(&self.prefix);
+        if !spec.description.is_empty() {
+            line.push_str(" - ");
+            line.push_str(&spec.description);
+        }
+        if !spec.aliases.is_empty() {
+            line.push_str(&format!("（别名: {}）", spec.aliases.join(", ")));
+        }
+        line
+    }
+
+    fn find(&self, name: &str) -> Option<&Arc<Command>> {
+        self.lookup
+            .get(&name.to_lowercase())
+            .map(|&index| &self.commands[index])
+    }
+
+    /// 去掉开头的 `@机器人` 和前缀，返回命令名和剩余的参数文本
+    fn split<'a>(&self, content: &'a str) -> Option<(&'a str, &'a str)> {
+        let content = strip_mentions(content).strip_prefix(self.prefix.as_str())?;
+        let (name, rest) = split_first_word(content);
+        if name.is_empty() {
+            return None;
+        }
+        Some((name, rest))
+    }
+
+    /// 解析消息并调用对应的命令，不是命令的消息直接忽略
+    pub async fn route(
+        &self,
+        message: IncomingMessage,
+        client: &QQClient,
+    ) -> Result<(), ClientError> {
+        let Some((name, rest)) = self
+            .split(message.content())
+            .map(|(name, rest)| (name.to_string(), rest.to_string()))
+        else {
+            return Ok(());
+        };
+        debug!("收到命令: {}", name);
+        let ctx = CommandContext {
+            command: name.clone(),
+            message,
+            client: client.clone(),
+        };
+        let (name, rest) = (name.as_str(), rest.as_str());
+
+        let Some(command) = self.find(name).cloned() else {
+            if self.help && HELP_NAMES.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+                return self.reply_help(&ctx, rest.trim()).await;
+            }
+            return match &self.fallback {
+                Some(fallback) => Self::finish(&ctx, fallback(ctx.clone()).await, None).await,
+                None => {
+                    let mut text = format!("未知命令: {}", name);
+                    if self.help {
+                        text.push_str(&format!("，发送 {}help 查看可用命令", self.prefix));
+                    }
+                    ctx.reply(text).await
+                }
+            };
+        };
+
+        let usage = command.spec.usage_line(&self.prefix);
+        let result = match Args::parse(rest) {
+            Ok(args) => (command.handler)(ctx.clone(), args).await,
+            Err(e) => Err(e.into()),
+        };
+        Self::finish(&ctx, result, Some(usage)).await
+    }
+
+    async fn reply_help(&self, ctx: &CommandContext, name: &str) -> Result<(), ClientError> {
+        let text = if name.is_empty() {
+            self.help_text()
+        } else {
+            let name = name.strip_prefix(self.prefix.as_str()).unwrap_or(name);
+            match self.find(name) {
+                Some(command) => self.describe(&command.spec),
+                None => format!("未知命令: {}", name),
+            }
+        };
+        ctx.reply(text).await
+    }
+
+    /// 参数错误时回复错误和用法，其余错误交给调用方
+    async fn finish(
+        ctx: &CommandContext,
+        result: Result<(), CommandError>,
+        usage: Option<String>,
+    ) -> Result<(), ClientError> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(CommandError::Args(e)) => {
+                warn!("命令 {} 参数错误: {}", ctx.command, e);
+                let mut text = format!("参数错误: {}", e);
+                if let Some(usage) = usage {
+                    text.push_str(&format!("\n用法: {}", usage));
+                }
+                ctx.reply(text).await
+            }
+            Err(CommandError::Client(e)) => Err(e),
+        }
+    }
+}
+
+/// 去掉消息开头的 `<@!id>` 提及和空白
+pub(crate) fn strip_mentions(content: &str) -> &str {
+    let mut content = content.trim_start();
+    while content.starts_with("<@")
+        && let Some(end) = content.find('>')
+    {
+        content = content[end + 1..].trim_start();
+    }
+    content
+}
+
+/// 按第一个空白拆分
+pub(crate) fn split_first_word(content: &str) -> (&str, &str) {
+    let end = content.find(char::is_whitespace).unwrap_or(content.len());
+    content.split_at(end)
+}
+
+#[async_trait]
+impl QQEvent for CommandRouter {
+    async fn on_group_at_message_create(
+        &self,
+        message: GroupMessage,
+        client: &QQClient,
+    ) -> Result<(), ClientError> {
+        self.route(IncomingMessage::Group(message), client).await
+    }
+
+    async fn on_c2c_message_create(
+        &self,
+        message: C2CMessage,
+        client: &QQClient,
+    ) -> Result<(), ClientError> {
+        self.route(IncomingMessage::C2C(message), client).await
+    }
+
+    async fn on_at_message_create(
+        &self,
+        message: ChannelMessage,
+        client: &QQClient,
+    ) -> Result<(), ClientError> {
+        self.route(IncomingMessage::Channel(message), client).await
+    }
+}
@@ -0,0 +1,134 @@
+use super::{Args, CommandContext, CommandRouter, CommandSpec, Rest};
+use crate::models::{command_error::ArgError, event::QQBotEvent};
+use crate::services::dispatcher::Dispatcher;
+use crate::test_support::{group_message_event, start_mock_api};
+
+#[test]
+fn args_parse_quotes_and_types() {
+    let mut args = Args::parse(r#"  北京 "New York" 3 yes 1.5 'a b' rest of  it "#).unwrap();
+    assert_eq!(args.remaining(), 9);
+    assert_eq!(args.parse_next::<String>().unwrap(), "北京");
+    assert_eq!(args.parse_next::<String>().unwrap(), "New York");
+    assert_eq!(args.parse_next::<(u32, bool)>().unwrap(), (3, true));
+    assert_eq!(args.parse_next::<Option<f64>>().unwrap(), Some(1.5));
+    assert_eq!(args.parse_next::<String>().unwrap(), "a b");
+    assert_eq!(
+        args.parse_next::<Rest>().unwrap(),
+        Rest("rest of  it".into())
+    );
+    assert!(args.is_empty());
+    assert_eq!(args.parse_next::<Option<u32>>().unwrap(), None);
+    assert!(args.parse_next::<Vec<u32>>().unwrap().is_empty());
+    args.finish().unwrap();
+
+    let mut args = Args::parse("1 2 x").unwrap();
+    assert_eq!(
+        args.parse_next::<Vec<i64>>(),
+        Err(ArgError::Invalid {
+            index: 3,
+            value: "x".into(),
+            expected: "整数",
+        })
+    );
+
+    let mut args = Args::parse("1").unwrap();
+    assert_eq!(args.parse_next::<(u8, u8)>(), Err(ArgError::Missing(2)));
+
+    let mut args = Args::parse("1 2").unwrap();
+    args.parse_next::<u8>().unwrap();
+    assert_eq!(args.finish(), Err(ArgError::TooMany("2".into())));
+
+    assert_eq!(
+        Args::parse(r#"say "hello"#).unwrap_err(),
+        ArgError::UnclosedQuote
+    );
+}
+
+fn weather_router() -> CommandRouter {
+    CommandRouter::new()
+        .with_command(
+            CommandSpec::new("weather")
+                .with_aliases(["天气"])
+                .with_usage("<city> [days]")
+                .with_description("查询天气"),
+            |ctx: CommandContext, (city, days): (String, Option<u32>)| async move {
+                ctx.reply(format!("{}:{}", city, days.unwrap_or(1))).await?;
+                Ok(())
+            },
+        )
+        .with_command(
+            CommandSpec::new("echo").with_usage("<text>"),
+            |ctx: CommandContext, Rest(text)| async move {
+                ctx.reply(text).await?;
+                Ok(())
+            },
+        )
+}
+
+async fn replies(router: CommandRouter, events: Vec<QQBotEvent>) -> Vec<String> {
+    let (client, api) = start_mock_api(Vec::new()).await;
+    let dispatcher = Dispatcher::new(client, std::sync::Arc::new(router)).without_dedupe();
+    for event in events {
+        dispatcher.handle(event).await.unwrap();
+    }
+    let requests = api.requests.lock().unwrap();
+    requests
+        .iter()
+        .map(|body| {
+            // 命令回复都是被动消息
+            assert!(body["msg_id"].is_string());
+            body["content"].as_str().unwrap().to_string()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn router_dispatches_commands() {
+    let events = vec![
+        group_message_event("1", " /weather 北京 3"),
+        group_message_event("2", "<@!bot> /天气 上海"),
+        group_message_event("3", " /WEATHER"),
+        group_message_event("4", " /weather 北京 x"),
+        group_message_event("5", " /echo  hi  there "),
+        group_message_event("6", " /unknown"),
+        group_message_event("7", " hello"),
+        group_message_event("8", " /help weather"),
+    ];
+    let replies = replies(weather_router(), events).await;
+    assert_eq!(
+        replies,
+        vec![
+            "北京:3".to_string(),
+            "上海:1".to_string(),
+            "参数错误: 缺少第 1 个参数\n用法: /weather <city> [days]".to_string(),
+            "参数错误: 第 2 个参数 `x` 不是有效的整数\n用法: /weather <city> [days]".to_string(),
+            "hi  there".to_string(),
+            "未知命令: unknown，发送 /help 查看可用命令".to_string(),
+            "/weather <city> [days] - 查询天气（别名: 天气）".to_string(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn router_help_and_fallback() {
+    let router =
+        weather_router()
+            .with_prefix("#")
+            .with_fallback(|ctx: CommandContext| async move {
+                ctx.reply(format!("fallback:{}", ctx.command())).await?;
+                Ok(())
+            });
+    assert_eq!(
+        router.help_text(),
+        "可用命令:\n#weather <city> [days] - 查询天气（别名: 天气）\n#echo <text>\n#help [命令] - 查看帮助"
+    );
+
+    let events = vec![
+        group_message_event("1", " /weather 北京"),
+        group_message_event("2", " #nope 1 2"),
+        group_message_event("3", " #帮助"),
+    ];
+    let help = router.help_text();
+    let replies = replies(router, events).await;
+    assert_eq!(replies, vec!["fallback:nope".to_string(), help]);
+}
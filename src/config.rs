@@ -1,23 +1,489 @@
-use dotenv::dotenv;
 use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use dotenv::dotenv;
+use serde::Deserialize;
+use strum::EnumString;
+
+use crate::models::config_error::ConfigError;
+
+/// 正式环境 API 地址
+pub const PRODUCTION_API_BASE_URL: &str = "https://api.sgroup.qq.com";
+/// 沙箱环境 API 地址
+pub const SANDBOX_API_BASE_URL: &str = "https://sandbox.api.sgroup.qq.com";
+/// 获取 AccessToken 的地址
+pub const DEFAULT_AUTH_URL: &str = "https://bots.qq.com/app/getAppAccessToken";
+
+/// 环境变量前缀，例如 `QQ_APP_ID`、`QQ_LISTEN_ADDR`
+pub const ENV_PREFIX: &str = "QQ_";
+/// 指定配置文件路径的环境变量
+pub const CONFIG_FILE_ENV: &str = "QQ_CONFIG_FILE";
+/// 未指定配置文件时，依次在当前目录查找的文件
+pub const DEFAULT_CONFIG_FILES: [&str; 3] = ["qq-bot.toml", "qq-bot.yaml", "qq-bot.yml"];
+
+/// 接收事件的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum RunMode {
+    /// 只连接 WebSocket 网关，不监听端口
+    Gateway,
+    /// 只通过 WebHook 接收事件，不连接网关
+    Webhook,
+    /// 同时使用网关和 WebHook
+    #[default]
+    Both,
+}
+
+impl RunMode {
+    pub fn uses_gateway(self) -> bool {
+        matches!(self, RunMode::Gateway | RunMode::Both)
+    }
+
+    pub fn uses_webhook(self) -> bool {
+        matches!(self, RunMode::Webhook | RunMode::Both)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub app_id: String,
     pub client_secret: String,
+    /// 是否使用沙箱环境
+    pub sandbox: bool,
+    /// 自定义 API 地址，设置后忽略 `sandbox`
+    pub api_base_url: Option<String>,
+    /// 自定义鉴权地址
+    pub auth_url: Option<String>,
+    /// Identify 时订阅的事件 intents
+    pub intents: u32,
+    /// 接收事件的方式
+    pub mode: RunMode,
+    /// WebHook 监听地址，仅网关模式下不使用
+    pub listen_addr: String,
+    /// WebHook 回调路径
+    pub webhook_path: String,
+    /// 网关连接相关的时间参数
+    pub timing: TimingConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            app_id: String::new(),
+            client_secret: String::new(),
+            sandbox: false,
+            api_base_url: None,
+            auth_url: None,
+            intents: 1 << 30,
+            mode: RunMode::default(),
+            listen_addr: "0.0.0.0:8080".to_string(),
+            webhook_path: "/".to_string(),
+            timing: TimingConfig::default(),
+        }
+    }
+}
+
+/// 网关连接相关的时间参数
+#[derive(Debug, Clone)]
+pub struct TimingConfig {
+    /// 发送心跳后等待 ACK 的超时时间（秒）
+    pub heartbeat_timeout_secs: u64,
+    /// 重连基础延迟（毫秒）
+    pub reconnect_base_delay_ms: u64,
+    /// 重连最大延迟（毫秒）
+    pub reconnect_max_delay_ms: u64,
+    /// 每次重连失败后延迟的增长倍数，不能小于 1
+    pub reconnect_multiplier: f64,
+    /// 重连延迟的随机抖动比例，范围 0.0..=1.0
+    pub reconnect_jitter: f64,
+    /// 连续重连失败多少次后暂停，0 表示不限
+    pub max_resume_retries: u32,
+    /// 连续重连失败后的暂停时间（秒）
+    pub resume_wait_secs: u64,
+    /// 连续重连失败达到上限后停止重连，而不是暂停后继续
+    pub stop_after_max_retries: bool,
+    /// 连续多少次无法建立连接后重新获取网关地址，0 表示不刷新
+    pub refresh_url_after_failures: u32,
+    /// 心跳平均 RTT 超过该值时视为连接质量下降并输出警告（毫秒），0 表示不检测
+    pub heartbeat_degraded_ms: u64,
+    /// 关闭时等待处理中事件完成的最长时间（秒）
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_timeout_secs: 7,
+            reconnect_base_delay_ms: 1000,
+            reconnect_max_delay_ms: 5000,
+            reconnect_multiplier: 2.0,
+            reconnect_jitter: 0.2,
+            max_resume_retries: 3,
+            resume_wait_secs: 30,
+            stop_after_max_retries: false,
+            refresh_url_after_failures: 3,
+            heartbeat_degraded_ms: 1000,
+            shutdown_timeout_secs: 10,
+        }
+    }
+}
+
+/// 配置文件和环境变量中的配置项，未设置的项保持默认值
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PartialConfig {
+    app_id: Option<String>,
+    client_secret: Option<String>,
+    sandbox: Option<bool>,
+    api_base_url: Option<String>,
+    auth_url: Option<String>,
+    intents: Option<u32>,
+    mode: Option<RunMode>,
+    listen_addr: Option<String>,
+    webhook_path: Option<String>,
+    timing: PartialTimingConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PartialTimingConfig {
+    heartbeat_timeout_secs: Option<u64>,
+    reconnect_base_delay_ms: Option<u64>,
+    reconnect_max_delay_ms: Option<u64>,
+    reconnect_multiplier: Option<f64>,
+    reconnect_jitter: Option<f64>,
+    max_resume_retries: Option<u32>,
+    resume_wait_secs: Option<u64>,
+    stop_after_max_retries: Option<bool>,
+    refresh_url_after_failures: Option<u32>,
+    heartbeat_degraded_ms: Option<u64>,
+    shutdown_timeout_secs: Option<u64>,
+}
+
+impl PartialConfig {
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Ok(toml::from_str(&content)?),
+            Some("yaml" | "yml") => Ok(serde_yaml::from_str(&content)?),
+            _ => Err(ConfigError::UnsupportedFormat(path.to_path_buf())),
+        }
+    }
+
+    fn from_env<F>(get: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let env = EnvSource(get);
+
+        Ok(Self {
+            app_id: env.var("APP_ID"),
+            client_secret: env.var("CLIENT_SECRET"),
+            sandbox: env.parse_bool("SANDBOX")?,
+            api_base_url: env.var("API_BASE_URL"),
+            auth_url: env.var("AUTH_URL"),
+            intents: env.parse("INTENTS")?,
+            mode: env.parse("MODE")?,
+            listen_addr: env.var("LISTEN_ADDR"),
+            webhook_path: env.var("WEBHOOK_PATH"),
+            timing: PartialTimingConfig {
+                heartbeat_timeout_secs: env.parse("HEARTBEAT_TIMEOUT_SECS")?,
+                reconnect_base_delay_ms: env.parse("RECONNECT_BASE_DELAY_MS")?,
+                reconnect_max_delay_ms: env.parse("RECONNECT_MAX_DELAY_MS")?,
+                reconnect_multiplier: env.parse("RECONNECT_MULTIPLIER")?,
+                reconnect_jitter: env.parse("RECONNECT_JITTER")?,
+                max_resume_retries: env.parse("MAX_RESUME_RETRIES")?,
+                resume_wait_secs: env.parse("RESUME_WAIT_SECS")?,
+                stop_after_max_retries: env.parse_bool("STOP_AFTER_MAX_RETRIES")?,
+                refresh_url_after_failures: env.parse("REFRESH_URL_AFTER_FAILURES")?,
+                heartbeat_degraded_ms: env.parse("HEARTBEAT_DEGRADED_MS")?,
+                shutdown_timeout_secs: env.parse("SHUTDOWN_TIMEOUT_SECS")?,
+            },
+        })
+    }
+
+    fn apply(self, config: &mut Config) {
+        set(&mut config.app_id, self.app_id);
+        set(&mut config.client_secret, self.client_secret);
+        set(&mut config.sandbox, self.sandbox);
+        set_opt(&mut config.api_base_url, self.api_base_url);
+        set_opt(&mut config.auth_url, self.auth_url);
+        set(&mut config.intents, self.intents);
+        set(&mut config.mode, self.mode);
+        set(&mut config.listen_addr, self.listen_addr);
+        set(&mut config.webhook_path, self.webhook_path);
+
+        let timing = &mut config.timing;
+        set(
+            &mut timing.heartbeat_timeout_secs,
+            self.timing.heartbeat_timeout_secs,
+        );
+        set(
+            &mut timing.reconnect_base_delay_ms,
+            self.timing.reconnect_base_delay_ms,
+        );
+        set(
+            &mut timing.reconnect_max_delay_ms,
+            self.timing.reconnect_max_delay_ms,
+        );
+        set(
+            &mut timing.max_resume_retries,
+            self.timing.max_resume_retries,
+        );
+        set(
+            &mut timing.reconnect_multiplier,
+            self.timing.reconnect_multiplier,
+        );
+        set(&mut timing.reconnect_jitter, self.timing.reconnect_jitter);
+        set(&mut timing.resume_wait_secs, self.timing.resume_wait_secs);
+        set(
+            &mut timing.stop_after_max_retries,
+            self.timing.stop_after_max_retries,
+        );
+        set(
+            &mut timing.refresh_url_after_failures,
+            self.timing.refresh_url_after_failures,
+        );
+        set(
+            &mut timing.heartbeat_degraded_ms,
+            self.timing.heartbeat_degraded_ms,
+        );
+        set(
+            &mut timing.shutdown_timeout_secs,
+            self.timing.shutdown_timeout_secs,
+        );
+    }
+}
+
+fn set<T>(target: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *target = value;
+    }
+}
+
+fn set_opt<T>(target: &mut Option<T>, value: Option<T>) {
+    if value.is_some() {
+        *target = value;
+    }
+}
+
+/// 带 `QQ_` 前缀的环境变量读取
+struct EnvSource<F>(F);
+
+impl<F: Fn(&str) -> Option<String>> EnvSource<F> {
+    fn var(&self, name: &str) -> Option<String> {
+        (self.0)(&format!("{ENV_PREFIX}{name}"))
+    }
+
+    fn parse<T: FromStr>(&self, name: &str) -> Result<Option<T>, ConfigError> {
+        self.var(name)
+            .map(|v| v.trim().parse().map_err(|_| invalid_value(name, v.clone())))
+            .transpose()
+    }
+
+    fn parse_bool(&self, name: &str) -> Result<Option<bool>, ConfigError> {
+        self.var(name)
+            .map(|v| match v.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => Ok(true),
+                "0" | "false" | "no" | "off" => Ok(false),
+                _ => Err(invalid_value(name, v)),
+            })
+            .transpose()
+    }
+}
+
+fn invalid_value(name: &str, value: String) -> ConfigError {
+    ConfigError::InvalidValue {
+        key: format!("{ENV_PREFIX}{name}"),
+        value,
+    }
 }
 
 impl Config {
-    pub fn from_env() -> Self {
+    /// 加载配置，优先级从低到高依次为：默认值、配置文件、环境变量
+    ///
+    /// 配置文件路径由 `QQ_CONFIG_FILE` 指定，未指定时依次查找当前目录下的
+    /// `qq-bot.toml`、`qq-bot.yaml`、`qq-bot.yml`，都不存在则跳过。
+    /// 环境变量统一使用 `QQ_` 前缀，也会读取 `.env` 文件。
+    pub fn load() -> Result<Self, ConfigError> {
         dotenv().ok();
 
-        Self {
-            app_id: env::var("QQ_APP_ID").expect("需要设置环境变量QQ_APP_ID"),
-            client_secret: env::var("QQ_CLIENT_SECRET").expect("需要设置环境变量QQ_CLIENT_SECRET"),
+        let file = match env::var(CONFIG_FILE_ENV) {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) => DEFAULT_CONFIG_FILES
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.is_file()),
+        };
+
+        Self::load_with(file.as_deref(), |name| env::var(name).ok())
+    }
+
+    /// 使用指定的配置文件和环境变量读取函数加载配置
+    pub fn load_with<F>(file: Option<&Path>, get_env: F) -> Result<Self, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut config = Config::default();
+        if let Some(path) = file {
+            PartialConfig::from_file(path)?.apply(&mut config);
+        }
+        PartialConfig::from_env(get_env)?.apply(&mut config);
+
+        if config.app_id.is_empty() {
+            return Err(ConfigError::Missing("app_id"));
+        }
+        if config.client_secret.is_empty() {
+            return Err(ConfigError::Missing("client_secret"));
+        }
+        let timing = &config.timing;
+        if !(timing.reconnect_multiplier.is_finite() && timing.reconnect_multiplier >= 1.0) {
+            return Err(invalid_value(
+                "RECONNECT_MULTIPLIER",
+                timing.reconnect_multiplier.to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&timing.reconnect_jitter) {
+            return Err(invalid_value(
+                "RECONNECT_JITTER",
+                timing.reconnect_jitter.to_string(),
+            ));
+        }
+
+        Ok(config)
+    }
+
+    /// 实际使用的 API 地址（不带结尾的 `/`）
+    pub fn api_base_url(&self) -> &str {
+        match &self.api_base_url {
+            Some(url) => url.trim_end_matches('/'),
+            None if self.sandbox => SANDBOX_API_BASE_URL,
+            None => PRODUCTION_API_BASE_URL,
         }
     }
+
+    /// 实际使用的鉴权地址
+    pub fn auth_url(&self) -> &str {
+        self.auth_url.as_deref().unwrap_or(DEFAULT_AUTH_URL)
+    }
+}
+
+pub fn get_config() -> Result<Config, ConfigError> {
+    Config::load()
 }
 
-pub fn get_config() -> Config {
-    Config::from_env()
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn env_of(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_missing_credentials() {
+        let err = Config::load_with(None, env_of(&[])).unwrap_err();
+        assert!(matches!(err, ConfigError::Missing("app_id")));
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let path = env::temp_dir().join(format!("qq-bot-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+app_id = "file_app_id"
+client_secret = "file_secret"
+listen_addr = "127.0.0.1:9000"
+mode = "webhook"
+
+[timing]
+heartbeat_timeout_secs = 3
+stop_after_max_retries = true
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load_with(
+            Some(&path),
+            env_of(&[
+                ("QQ_APP_ID", "env_app_id"),
+                ("QQ_SANDBOX", "true"),
+                ("QQ_MODE", "Gateway"),
+                ("QQ_REFRESH_URL_AFTER_FAILURES", "0"),
+            ]),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(config.app_id, "env_app_id");
+        assert_eq!(config.client_secret, "file_secret");
+        assert_eq!(config.listen_addr, "127.0.0.1:9000");
+        assert_eq!(config.timing.heartbeat_timeout_secs, 3);
+        assert_eq!(config.timing.resume_wait_secs, 30);
+        assert!(config.timing.stop_after_max_retries);
+        assert_eq!(config.timing.refresh_url_after_failures, 0);
+        assert!(config.sandbox);
+        assert_eq!(config.mode, RunMode::Gateway);
+    }
+
+    #[test]
+    fn test_invalid_env_value() {
+        let err = Config::load_with(
+            None,
+            env_of(&[
+                ("QQ_APP_ID", "id"),
+                ("QQ_CLIENT_SECRET", "secret"),
+                ("QQ_INTENTS", "all"),
+            ]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidValue { key, .. } if key == "QQ_INTENTS"));
+    }
+
+    #[test]
+    fn test_invalid_reconnect_backoff() {
+        for (name, value) in [
+            ("QQ_RECONNECT_MULTIPLIER", "-2"),
+            ("QQ_RECONNECT_MULTIPLIER", "NaN"),
+            ("QQ_RECONNECT_JITTER", "-0.1"),
+            ("QQ_RECONNECT_JITTER", "1.5"),
+            ("QQ_RECONNECT_JITTER", "NaN"),
+        ] {
+            let err = Config::load_with(
+                None,
+                env_of(&[
+                    ("QQ_APP_ID", "id"),
+                    ("QQ_CLIENT_SECRET", "secret"),
+                    (name, value),
+                ]),
+            )
+            .unwrap_err();
+            assert!(
+                matches!(&err, ConfigError::InvalidValue { key, .. } if key == name),
+                "{name}={value}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sandbox_base_url() {
+        let config = Config {
+            sandbox: true,
+            ..Default::default()
+        };
+        assert_eq!(config.api_base_url(), "https://sandbox.api.sgroup.qq.com");
+    }
 }
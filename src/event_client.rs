@@ -4,9 +4,12 @@ use tracing::debug;
 use crate::{
     models::{
         client_error::ClientError,
-        message::{C2CMessage, GroupMessage, PostMessageBody},
+        handler_error::HandlerError,
+        message::{
+            C2CMessage, ChannelMessage, GroupMessage, PostChannelMessageBody, PostMessageBody,
+        },
     },
-    services::client::QQClient,
+    services::{client::QQClient, dispatcher::Event},
 };
 
 #[async_trait]
@@ -26,6 +29,17 @@ pub trait QQEvent: Send + Sync {
     ) -> Result<(), ClientError> {
         Ok(())
     }
+
+    async fn on_at_message_create(
+        &self,
+        _message: ChannelMessage,
+        _client: &QQClient,
+    ) -> Result<(), ClientError> {
+        Ok(())
+    }
+
+    /// 处理事件失败或 panic 后调用，可以用来告知用户出错了；错误已由分发器记录日志
+    async fn on_error(&self, _event: &Event, _error: &HandlerError, _client: &QQClient) {}
 }
 
 pub struct DefaultEventHandler;
@@ -65,4 +79,24 @@ impl QQEvent for DefaultEventHandler {
 
         Ok(())
     }
+
+    async fn on_at_message_create(
+        &self,
+        message: ChannelMessage,
+        client: &QQClient,
+    ) -> Result<(), ClientError> {
+        debug!("Handling AtMessageCreate event");
+        let body = PostChannelMessageBody {
+            content: format!("收到消息: {}", message.content),
+            image: None,
+            msg_id: Some(message.id.clone()),
+            event_id: None,
+        };
+
+        client
+            .post_channel_message(&message.channel_id, body)
+            .await?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,137 @@
+use crate::{
+    commands::{
+        Args, FromArgs,
+        router::{split_first_word, strip_mentions},
+    },
+    handler::router::EventContext,
+    models::{
+        handler_error::HandlerError,
+        message::{C2CMessage, ChannelMessage, GroupMessage, IncomingMessage},
+    },
+    services::{client::QQClient, dispatcher::Event},
+};
+
+/// 可以从事件中提取的类型，作为处理函数的参数
+///
+/// 提取失败时处理函数不会被调用；需要可选提取时使用 `Option<T>`。
+pub trait FromEvent<S>: Sized {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError>;
+}
+
+fn rejected<S>(extractor: &'static str, cx: &EventContext<S>) -> HandlerError {
+    HandlerError::Rejected {
+        extractor,
+        event: cx.event().kind().into(),
+    }
+}
+
+impl<S, T: FromEvent<S>> FromEvent<S> for Option<T> {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        Ok(T::from_event(cx).ok())
+    }
+}
+
+impl<S> FromEvent<S> for Event {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        Ok(cx.event().clone())
+    }
+}
+
+impl<S> FromEvent<S> for QQClient {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        Ok(cx.client().clone())
+    }
+}
+
+/// 群聊、单聊或频道消息
+impl<S> FromEvent<S> for IncomingMessage {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        cx.event()
+            .message()
+            .ok_or_else(|| rejected("IncomingMessage", cx))
+    }
+}
+
+impl<S> FromEvent<S> for GroupMessage {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        match cx.event() {
+            Event::GroupAtMessageCreate(m) => Ok(m.clone()),
+            _ => Err(rejected("GroupMessage", cx)),
+        }
+    }
+}
+
+impl<S> FromEvent<S> for C2CMessage {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        match cx.event() {
+            Event::C2CMessageCreate(m) => Ok(m.clone()),
+            _ => Err(rejected("C2CMessage", cx)),
+        }
+    }
+}
+
+impl<S> FromEvent<S> for ChannelMessage {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        match cx.event() {
+            Event::AtMessageCreate(m) => Ok(m.clone()),
+            _ => Err(rejected("ChannelMessage", cx)),
+        }
+    }
+}
+
+/// 发送者标识：群成员的 member_openid、单聊用户的 user_openid 或频道用户 id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SenderOpenid(pub String);
+
+impl<S> FromEvent<S> for SenderOpenid {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        let message = cx
+            .event()
+            .message()
+            .ok_or_else(|| rejected("SenderOpenid", cx))?;
+        Ok(SenderOpenid(message.sender_id().to_string()))
+    }
+}
+
+/// 群聊消息的 group_openid，其他事件提取失败
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupOpenid(pub String);
+
+impl<S> FromEvent<S> for GroupOpenid {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        match cx.event() {
+            Event::GroupAtMessageCreate(m) => Ok(GroupOpenid(m.group_openid.clone())),
+            _ => Err(rejected("GroupOpenid", cx)),
+        }
+    }
+}
+
+/// 路由器的共享状态，通过 [`EventRouter::with_state`](super::EventRouter::with_state) 设置
+#[derive(Debug, Clone)]
+pub struct State<S>(pub S);
+
+impl<S: Clone> FromEvent<S> for State<S> {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        Ok(State(cx.state().clone()))
+    }
+}
+
+/// 命令参数：去掉 `@机器人` 和第一个词（命令名）后，剩余内容按 [`FromArgs`] 解析
+///
+/// 解析失败时返回 [`HandlerError::Args`]，路由器会回复错误信息。
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandArgs<T>(pub T);
+
+impl<S, T: FromArgs> FromEvent<S> for CommandArgs<T> {
+    fn from_event(cx: &EventContext<S>) -> Result<Self, HandlerError> {
+        let message = cx
+            .event()
+            .message()
+            .ok_or_else(|| rejected("CommandArgs", cx))?;
+        let (_, rest) = split_first_word(strip_mentions(message.content()));
+        let mut args = Args::parse(rest)?;
+        let value = T::from_args(&mut args)?;
+        args.finish()?;
+        Ok(CommandArgs(value))
+    }
+}
@@ -0,0 +1,7 @@
+mod extract;
+mod router;
+#[cfg(test)]
+mod tests;
+
+pub use extract::{CommandArgs, FromEvent, GroupOpenid, SenderOpenid, State};
+pub use router::{EventContext, EventRouter, Handler};
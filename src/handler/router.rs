@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{debug, error, warn};
+
+use crate::{
+    event_client::QQEvent,
+    handler::extract::FromEvent,
+    models::{
+        client_error::ClientError,
+        handler_error::HandlerError,
+        message::{C2CMessage, ChannelMessage, GroupMessage},
+    },
+    services::{
+        client::QQClient,
+        dispatcher::{Event, MessageKind},
+    },
+};
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+type BoxedHandler<S> =
+    Arc<dyn Fn(EventContext<S>) -> BoxFuture<Result<(), HandlerError>> + Send + Sync>;
+
+/// 处理函数执行时可用的上下文，提取器从这里取值
+pub struct EventContext<S> {
+    event: Event,
+    client: QQClient,
+    state: S,
+}
+
+impl<S> EventContext<S> {
+    pub fn event(&self) -> &Event {
+        &self.event
+    }
+
+    pub fn client(&self) -> &QQClient {
+        &self.client
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+}
+
+/// 可以注册到 [`EventRouter`] 的处理函数
+///
+/// 为参数都实现了 [`FromEvent`]、返回 `Result<(), HandlerError>` 的异步函数自动实现，
+/// 最多 8 个参数。`T` 只用于区分不同参数个数的实现。
+pub trait Handler<T, S>: Clone + Send + Sync + 'static {
+    fn call(&self, cx: EventContext<S>) -> BoxFuture<Result<(), HandlerError>>;
+}
+
+macro_rules! impl_handler {
+    ($($ty:ident),*) => {
+        #[allow(non_snake_case, unused_variables, unused_labels)]
+        impl<F, Fut, S, $($ty,)*> Handler<($($ty,)*), S> for F
+        where
+            F: Fn($($ty,)*) -> Fut + Clone + Send + Sync + 'static,
+            Fut: Future<Output = Result<(), HandlerError>> + Send + 'static,
+            $($ty: FromEvent<S> + Send + 'static,)*
+        {
+            fn call(&self, cx: EventContext<S>) -> BoxFuture<Result<(), HandlerError>> {
+                let extracted: Result<($($ty,)*), HandlerError> = 'extract: {
+                    Ok(($(
+                        match <$ty as FromEvent<S>>::from_event(&cx) {
+                            Ok(value) => value,
+                            Err(e) => break 'extract Err(e),
+                        },
+                    )*))
+                };
+                let handler = self.clone();
+                Box::pin(async move {
+                    let ($($ty,)*) = extracted?;
+                    handler($($ty,)*).await
+                })
+            }
+        }
+    };
+}
+
+impl_handler!();
+impl_handler!(T1);
+impl_handler!(T1, T2);
+impl_handler!(T1, T2, T3);
+impl_handler!(T1, T2, T3, T4);
+impl_handler!(T1, T2, T3, T4, T5);
+impl_handler!(T1, T2, T3, T4, T5, T6);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7);
+impl_handler!(T1, T2, T3, T4, T5, T6, T7, T8);
+
+/// 按事件类型注册处理函数的路由器，用法类似 axum 的 `Router`
+///
+/// 处理函数通过参数类型声明需要的数据，例如 [`SenderOpenid`](super::SenderOpenid)、
+/// [`CommandArgs`](super::CommandArgs)、[`State`](super::State) 或 [`QQClient`]。
+/// 实现了 [`QQEvent`]，可以直接传给 `ServerBuilder::with_event_handler`。
+///
+/// ```ignore
+/// async fn weather(
+///     message: IncomingMessage,
+///     client: QQClient,
+///     CommandArgs((city, days)): CommandArgs<(String, Option<u32>)>,
+/// ) -> Result<(), HandlerError> {
+///     client.reply(&message, format!("{} 未来 {} 天晴", city, days.unwrap_or(1))).await?;
+///     Ok(())
+/// }
+///
+/// let router = EventRouter::new().on(MessageKind::GroupAtMessageCreate, weather);
+/// ```
+#[derive(Clone)]
+pub struct EventRouter<S = ()> {
+    routes: HashMap<MessageKind, BoxedHandler<S>>,
+    state: S,
+}
+
+impl Default for EventRouter<()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventRouter<()> {
+    pub fn new() -> Self {
+        Self::with_state(())
+    }
+}
+
+impl<S> EventRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    /// 创建带共享状态的路由器，处理函数通过 [`State`](super::State) 提取
+    pub fn with_state(state: S) -> Self {
+        Self {
+            routes: HashMap::new(),
+            state,
+        }
+    }
+
+    /// 注册某类消息事件的处理函数，同一类事件以后注册的为准
+    pub fn on<T, H>(mut self, kind: MessageKind, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+    {
+        let handler: BoxedHandler<S> = Arc::new(move |cx| handler.call(cx));
+        self.routes.insert(kind, handler);
+        self
+    }
+
+    /// 为群聊、单聊和频道消息注册同一个处理函数
+    pub fn on_message<T, H>(self, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+    {
+        self.on(MessageKind::GroupAtMessageCreate, handler.clone())
+            .on(MessageKind::C2CMessageCreate, handler.clone())
+            .on(MessageKind::AtMessageCreate, handler)
+    }
+
+    /// 调用事件对应的处理函数，没有注册的事件直接忽略
+    ///
+    /// 参数解析失败时回复错误信息，提取失败只记录日志。
+    pub async fn call(&self, event: Event, client: &QQClient) -> Result<(), ClientError> {
+        let kind = event.kind();
+        let Some(handler) = self.routes.get(&kind) else {
+            debug!("没有注册 {:?} 的处理函数", kind);
+            return Ok(());
+        };
+        let message = event.message();
+        let cx = EventContext {
+            event,
+            client: client.clone(),
+            state: self.state.clone(),
+        };
+
+        match handler(cx).await {
+            Ok(()) => Ok(()),
+            Err(HandlerError::Args(e)) => {
+                warn!("{:?} 参数错误: {}", kind, e);
+                match message {
+                    Some(message) => client.reply(&message, format!("参数错误: {}", e)).await,
+                    None => Ok(()),
+                }
+            }
+            Err(HandlerError::Client(e)) => Err(e),
+            Err(e) => {
+                error!("{}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S> QQEvent for EventRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    async fn on_group_at_message_create(
+        &self,
+        message: GroupMessage,
+        client: &QQClient,
+    ) -> Result<(), ClientError> {
+        self.call(Event::GroupAtMessageCreate(message), client)
+            .await
+    }
+
+    async fn on_c2c_message_create(
+        &self,
+        message: C2CMessage,
+        client: &QQClient,
+    ) -> Result<(), ClientError> {
+        self.call(Event::C2CMessageCreate(message), client).await
+    }
+
+    async fn on_at_message_create(
+        &self,
+        message: ChannelMessage,
+        client: &QQClient,
+    ) -> Result<(), ClientError> {
+        self.call(Event::AtMessageCreate(message), client).await
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::{Arc, Mutex};
+
+use super::{CommandArgs, EventRouter, GroupOpenid, SenderOpenid, State};
+use crate::models::{handler_error::HandlerError, message::IncomingMessage};
+use crate::services::client::QQClient;
+use crate::services::dispatcher::{Dispatcher, MessageKind};
+use crate::test_support::{c2c_message_event, group_message_event, start_mock_api};
+
+type Log = Arc<Mutex<Vec<String>>>;
+
+async fn weather(
+    message: IncomingMessage,
+    client: QQClient,
+    CommandArgs((city, days)): CommandArgs<(String, Option<u32>)>,
+) -> Result<(), HandlerError> {
+    client
+        .reply(&message, format!("{}:{}", city, days.unwrap_or(1)))
+        .await?;
+    Ok(())
+}
+
+async fn record(
+    State(log): State<Log>,
+    SenderOpenid(sender): SenderOpenid,
+    group: Option<GroupOpenid>,
+) -> Result<(), HandlerError> {
+    let group = group.map(|GroupOpenid(g)| g).unwrap_or_default();
+    log.lock().unwrap().push(format!("{}@{}", sender, group));
+    Ok(())
+}
+
+#[tokio::test]
+async fn router_extracts_by_event_kind() {
+    let (client, api) = start_mock_api(Vec::new()).await;
+    let log = Log::default();
+    let router = EventRouter::with_state(log.clone())
+        .on_message(record)
+        .on(MessageKind::GroupAtMessageCreate, weather);
+    let dispatcher = Dispatcher::new(client, Arc::new(router)).without_dedupe();
+
+    for event in [
+        group_message_event("1", " /weather 北京 3"),
+        group_message_event("2", " /weather"),
+        c2c_message_event("3", "hi"),
+    ] {
+        dispatcher.handle(event).await.unwrap();
+    }
+
+    // 群消息由后注册的 weather 处理，参数错误时回复错误信息
+    let replies: Vec<_> = api
+        .requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|body| body["content"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(replies, vec!["北京:3", "参数错误: 缺少第 1 个参数"]);
+    assert_eq!(*log.lock().unwrap(), vec!["user_openid@".to_string()]);
+}
+
+#[tokio::test]
+async fn rejected_extractor_skips_handler() {
+    let (client, api) = start_mock_api(Vec::new()).await;
+    let called = Arc::new(Mutex::new(false));
+    let router = EventRouter::with_state(called.clone()).on(
+        MessageKind::C2CMessageCreate,
+        |State(called): State<Arc<Mutex<bool>>>, _group: GroupOpenid| async move {
+            *called.lock().unwrap() = true;
+            Ok(())
+        },
+    );
+
+    router
+        .call(
+            crate::services::dispatcher::Event::decode(c2c_message_event("1", "hi"))
+                .unwrap()
+                .unwrap(),
+            &client,
+        )
+        .await
+        .unwrap();
+    assert!(!*called.lock().unwrap());
+    assert!(api.requests.lock().unwrap().is_empty());
+}
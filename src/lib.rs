@@ -1,5 +1,9 @@
+pub mod commands;
 pub mod config;
 pub mod event_client;
+pub mod handler;
 pub mod models;
 pub mod services;
+#[cfg(test)]
+mod test_support;
 mod utils;
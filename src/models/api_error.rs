@@ -0,0 +1,140 @@
+use std::fmt;
+
+use reqwest::{StatusCode, header::HeaderMap};
+use serde::Deserialize;
+
+/// 开放平台返回的链路追踪 ID 头，反馈问题时需要提供
+pub const TRACE_ID_HEADER: &str = "X-Tps-trace-ID";
+
+/// 开放平台返回的错误信息
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    /// HTTP 状态码
+    pub status: StatusCode,
+    /// 错误码
+    pub code: Option<u32>,
+    /// 错误信息
+    pub message: Option<String>,
+    /// 业务错误码
+    pub err_code: Option<u32>,
+    /// 链路追踪 ID，优先取响应体中的值，其次取响应头
+    pub trace_id: Option<String>,
+    /// 原始响应体
+    pub body: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ApiErrorBody {
+    code: Option<u32>,
+    message: Option<String>,
+    err_code: Option<u32>,
+    trace_id: Option<String>,
+}
+
+/// 已知的错误类别，便于调用方按类型分支处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiErrorKind {
+    /// 鉴权失败或 Token 过期
+    Unauthorized,
+    /// 没有权限
+    NoPermission,
+    /// 请求频率超限
+    RateLimited,
+    /// 被动回复的 msg_id / event_id 已过期
+    PassiveReplyExpired,
+    /// 消息内容未通过审核
+    ContentBlocked,
+    /// 消息被去重（msg_seq 重复）
+    Duplicated,
+    /// 服务端错误
+    ServerError,
+    /// 其他错误
+    Other,
+}
+
+impl ApiError {
+    /// 根据状态码、响应头和响应体构造错误
+    pub fn from_parts(status: StatusCode, headers: &HeaderMap, body: String) -> Self {
+        let parsed = serde_json::from_str::<ApiErrorBody>(&body).unwrap_or_default();
+        let trace_id = parsed.trace_id.or_else(|| {
+            headers
+                .get(TRACE_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned)
+        });
+
+        Self {
+            status,
+            code: parsed.code,
+            message: parsed.message,
+            err_code: parsed.err_code,
+            trace_id,
+            body,
+        }
+    }
+
+    /// 读取失败响应并构造错误
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.text().await.unwrap_or_default();
+        Self::from_parts(status, &headers, body)
+    }
+
+    /// 错误类别，优先根据错误码判断，其次根据 HTTP 状态码
+    pub fn kind(&self) -> ApiErrorKind {
+        let by_code = [self.code, self.err_code]
+            .into_iter()
+            .flatten()
+            .find_map(kind_of_code);
+        if let Some(kind) = by_code {
+            return kind;
+        }
+
+        match self.status {
+            StatusCode::UNAUTHORIZED => ApiErrorKind::Unauthorized,
+            StatusCode::FORBIDDEN => ApiErrorKind::NoPermission,
+            StatusCode::TOO_MANY_REQUESTS => ApiErrorKind::RateLimited,
+            s if s.is_server_error() => ApiErrorKind::ServerError,
+            _ => ApiErrorKind::Other,
+        }
+    }
+}
+
+/// 已知错误码到错误类别的映射
+///
+/// 错误码取自开放平台文档的错误码表：
+/// <https://bot.q.qq.com/wiki/develop/api-v2/openapi/error/error.html>
+fn kind_of_code(code: u32) -> Option<ApiErrorKind> {
+    match code {
+        11241..=11244 => Some(ApiErrorKind::Unauthorized),
+        11251..=11256 | 11264 | 11265 => Some(ApiErrorKind::NoPermission),
+        22009 | 20028 | 304045..=304048 => Some(ApiErrorKind::RateLimited),
+        40034024 | 40034025 | 304027 => Some(ApiErrorKind::PassiveReplyExpired),
+        304003 | 40054010 => Some(ApiErrorKind::ContentBlocked),
+        40054005 => Some(ApiErrorKind::Duplicated),
+        _ => None,
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "status={}", self.status)?;
+        if let Some(code) = self.code {
+            write!(f, ", code={code}")?;
+        }
+        if let Some(err_code) = self.err_code {
+            write!(f, ", err_code={err_code}")?;
+        }
+        match &self.message {
+            Some(message) => write!(f, ", message={message}")?,
+            None => write!(f, ", response={}", self.body)?,
+        }
+        if let Some(trace_id) = &self.trace_id {
+            write!(f, ", trace_id={trace_id}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
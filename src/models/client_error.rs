@@ -1,5 +1,9 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
+use super::api_error::{ApiError, ApiErrorKind};
+
 #[derive(Error, Debug)]
 pub enum ClientError {
     #[error("Network request failed: {0}")]
@@ -20,12 +24,30 @@ pub enum ClientError {
         response: String,
     },
 
+    #[error("API request failed: {0}")]
+    Api(#[from] ApiError),
+
+    #[error("Passive reply window expired: {elapsed:?} since receipt, window is {window:?}")]
+    PassiveReplyExpired { elapsed: Duration, window: Duration },
+
+    #[deprecated(note = "不再返回，发送消息失败时返回 `ClientError::Api`")]
     #[error("Failed to post message: {0}")]
     PostMessageFailed(String),
 
+    #[deprecated(note = "不再返回，获取网关地址失败时返回 `ClientError::Api`")]
     #[error("Failed to get WSS endpoint: {0}")]
     GetWssEndpointFailed(String),
 
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
+
+impl ClientError {
+    /// 如果是开放平台返回的错误，返回其错误类别
+    pub fn api_kind(&self) -> Option<ApiErrorKind> {
+        match self {
+            ClientError::Api(err) => Some(err.kind()),
+            _ => None,
+        }
+    }
+}
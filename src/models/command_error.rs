@@ -0,0 +1,32 @@
+use thiserror::Error;
+
+use super::client_error::ClientError;
+
+/// 命令参数解析错误
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ArgError {
+    #[error("缺少第 {0} 个参数")]
+    Missing(usize),
+
+    #[error("第 {index} 个参数 `{value}` 不是有效的{expected}")]
+    Invalid {
+        index: usize,
+        value: String,
+        expected: &'static str,
+    },
+
+    #[error("多余的参数: {0}")]
+    TooMany(String),
+
+    #[error("引号未闭合")]
+    UnclosedQuote,
+}
+
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("Invalid arguments: {0}")]
+    Args(#[from] ArgError),
+
+    #[error("Client error: {0}")]
+    Client(#[from] ClientError),
+}
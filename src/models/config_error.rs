@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Missing required setting: {0}")]
+    Missing(&'static str),
+
+    #[error("Invalid value for {key}: {value}")]
+    InvalidValue { key: String, value: String },
+
+    #[error("Failed to read config file {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse TOML config: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("Failed to parse YAML config: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("Unsupported config file format: {0:?}")]
+    UnsupportedFormat(PathBuf),
+}
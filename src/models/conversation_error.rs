@@ -0,0 +1,12 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConversationError {
+    #[error("No message received within {0:?}")]
+    Timeout(Duration),
+
+    #[error("Conversation is already waiting for a message")]
+    AlreadyWaiting,
+}
@@ -4,13 +4,16 @@ use axum::{
 };
 use thiserror::Error;
 
-use super::client_error::ClientError;
+use super::{client_error::ClientError, handler_error::HandlerError};
 
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("Client error: {0}")]
     ClientError(#[from] ClientError),
 
+    #[error("Handler error: {0}")]
+    HandlerError(#[from] HandlerError),
+
     #[error("Database error: {0}")]
     DatabaseError(String),
 
@@ -0,0 +1,24 @@
+use serde::Deserialize;
+
+/// `GET /gateway/bot` 返回的网关信息
+#[derive(Debug, Clone, Deserialize)]
+pub struct GatewayBot {
+    /// WebSocket 地址
+    pub url: String,
+    /// 建议的分片数
+    pub shards: u32,
+    /// 创建 Session 的限制
+    pub session_start_limit: SessionStartLimit,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionStartLimit {
+    /// 每 24 小时可创建 Session 数
+    pub total: u32,
+    /// 目前还可以创建的 Session 数
+    pub remaining: u32,
+    /// 重置计数的剩余时间（毫秒）
+    pub reset_after: u64,
+    /// 每 5 秒可以创建的 Session 数
+    pub max_concurrency: u32,
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+use super::{
+    client_error::ClientError, command_error::ArgError, conversation_error::ConversationError,
+};
+
+/// 事件处理函数的错误，包括提取参数失败
+#[derive(Error, Debug)]
+pub enum HandlerError {
+    #[error("Cannot extract {extractor} from {event} event")]
+    Rejected {
+        extractor: &'static str,
+        event: &'static str,
+    },
+
+    #[error("Invalid arguments: {0}")]
+    Args(#[from] ArgError),
+
+    #[error("Client error: {0}")]
+    Client(#[from] ClientError),
+
+    #[error("Conversation error: {0}")]
+    Conversation(#[from] ConversationError),
+
+    #[error("Handler panicked: {0}")]
+    Panicked(String),
+
+    #[error("Handler timed out after {0:?}")]
+    Timeout(Duration),
+}
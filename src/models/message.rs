@@ -1,4 +1,12 @@
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// 群聊和频道消息被动回复的有效期
+pub const GROUP_REPLY_WINDOW: Duration = Duration::from_secs(5 * 60);
+/// 单聊消息被动回复的有效期
+pub const C2C_REPLY_WINDOW: Duration = Duration::from_secs(60 * 60);
 
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct PostMessageBody {
@@ -52,6 +60,18 @@ impl PostMessageBody {
         self.is_wakeup = Some(is_wakeup);
         self
     }
+
+    pub fn msg_seq(&self) -> Option<&str> {
+        self.msg_seq.as_deref()
+    }
+
+    /// 未设置 msg_seq 时生成一个随机值，保证重试时使用同一个 msg_seq，由服务端去重
+    pub(crate) fn ensure_msg_seq(&mut self) {
+        if self.msg_seq.is_none() {
+            let seq = rand::random_range(1..=u32::MAX);
+            self.msg_seq = Some(seq.to_string());
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -72,6 +92,9 @@ pub struct GroupMessage {
     pub message_scene: MessageScene,
     pub message_type: u8,
     pub timestamp: String,
+    /// 收到事件的时间，用于判断被动回复是否过期
+    #[serde(skip, default = "Instant::now")]
+    pub received_at: Instant,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -89,6 +112,9 @@ pub struct C2CMessage {
     pub message_scene: MessageScene,
     pub message_type: u8,
     pub timestamp: String,
+    /// 收到事件的时间，用于判断被动回复是否过期
+    #[serde(skip, default = "Instant::now")]
+    pub received_at: Instant,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -102,3 +128,87 @@ pub struct C2CAuthor {
 pub struct MessageScene {
     pub source: String,
 }
+
+/// 频道内 @机器人 的消息（AT_MESSAGE_CREATE）
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelMessage {
+    pub author: ChannelAuthor,
+    pub channel_id: String,
+    /// 消息内容，以 `<@!机器人id>` 开头
+    pub content: String,
+    pub guild_id: String,
+    pub id: String,
+    pub timestamp: String,
+    /// 收到事件的时间，用于判断被动回复是否过期
+    #[serde(skip, default = "Instant::now")]
+    pub received_at: Instant,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChannelAuthor {
+    pub id: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub bot: bool,
+}
+
+/// 收到的用户消息，统一群聊、单聊和频道三种场景
+#[derive(Debug, Clone)]
+pub enum IncomingMessage {
+    Group(GroupMessage),
+    C2C(C2CMessage),
+    Channel(ChannelMessage),
+}
+
+impl IncomingMessage {
+    /// 消息 id，被动回复时使用
+    pub fn id(&self) -> &str {
+        match self {
+            IncomingMessage::Group(m) => &m.id,
+            IncomingMessage::C2C(m) => &m.id,
+            IncomingMessage::Channel(m) => &m.id,
+        }
+    }
+
+    pub fn content(&self) -> &str {
+        match self {
+            IncomingMessage::Group(m) => &m.content,
+            IncomingMessage::C2C(m) => &m.content,
+            IncomingMessage::Channel(m) => &m.content,
+        }
+    }
+
+    /// 收到消息的时间
+    pub fn received_at(&self) -> Instant {
+        match self {
+            IncomingMessage::Group(m) => m.received_at,
+            IncomingMessage::C2C(m) => m.received_at,
+            IncomingMessage::Channel(m) => m.received_at,
+        }
+    }
+
+    /// 被动回复的有效期，从收到消息开始计算
+    pub fn reply_window(&self) -> Duration {
+        match self {
+            IncomingMessage::C2C(_) => C2C_REPLY_WINDOW,
+            IncomingMessage::Group(_) | IncomingMessage::Channel(_) => GROUP_REPLY_WINDOW,
+        }
+    }
+
+    /// 被动回复有效期的剩余时间，已过期返回 `None`
+    pub fn reply_remaining(&self) -> Option<Duration> {
+        self.reply_window()
+            .checked_sub(self.received_at().elapsed())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// 发送者标识：群成员 member_openid、用户 user_openid 或频道用户 id
+    pub fn sender_id(&self) -> &str {
+        match self {
+            IncomingMessage::Group(m) => &m.author.member_openid,
+            IncomingMessage::C2C(m) => &m.author.user_openid,
+            IncomingMessage::Channel(m) => &m.author.id,
+        }
+    }
+}
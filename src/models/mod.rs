@@ -1,6 +1,13 @@
+pub mod api_error;
 pub mod auth;
 pub mod client_error;
+pub mod command_error;
+pub mod config_error;
+pub mod conversation_error;
 pub mod error;
 pub mod event;
+pub mod gateway;
+pub mod handler_error;
 pub mod message;
+pub mod rate_limit_error;
 pub mod server_error;
@@ -0,0 +1,10 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitError {
+    #[error("Token bucket burst must be greater than 0")]
+    ZeroBurst,
+
+    #[error("Token bucket refill rate must be positive and finite, got {0}")]
+    InvalidRefillRate(f64),
+}
@@ -1,30 +1,32 @@
 use std::sync::{Arc, RwLock};
 
-use reqwest::{
-    Response,
-    header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue},
-};
-use serde::{Deserialize, Serialize};
-use tracing::{debug, error};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::time::sleep;
+use tracing::{debug, error, warn};
 
 use crate::{
     config::Config,
     models::{
+        api_error::{ApiError, ApiErrorKind},
         auth::AuthToken,
         client_error::ClientError,
-        message::{PostChannelMessageBody, PostMessageBody},
+        gateway::GatewayBot,
+        message::{IncomingMessage, PostChannelMessageBody, PostMessageBody},
+    },
+    services::{
+        rate_limit::{RateLimitConfig, RateLimiter, Route},
+        retry::RetryPolicy,
     },
 };
 
-// 固定的QQ API地址
-const QQ_BASE_URL: &str = "https://api.sgroup.qq.com";
-const QQ_AUTH_URL: &str = "https://bots.qq.com/app/getAppAccessToken";
-
 #[derive(Clone)]
 pub struct QQClient {
     client: reqwest::Client,
     config: Config,
     token: Arc<RwLock<Option<String>>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    retry_policy: Option<RetryPolicy>,
 }
 
 impl QQClient {
@@ -41,16 +43,49 @@ impl QQClient {
             client,
             config,
             token: Arc::new(RwLock::new(None)),
+            rate_limiter: None,
+            retry_policy: None,
         }
     }
 
+    /// 启用失败重试，默认不重试
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// 按 (路由, 目标) 启用限流，默认不限流
+    pub fn with_rate_limit(mut self, config: RateLimitConfig) -> Self {
+        self.rate_limiter = Some(Arc::new(RateLimiter::new(config)));
+        self
+    }
+
+    /// 关闭已启用的客户端限流
+    pub fn without_rate_limit(mut self) -> Self {
+        self.rate_limiter = None;
+        self
+    }
+
+    /// 当前因限流而排队的请求数
+    pub fn rate_limit_queue_depth(&self) -> usize {
+        self.rate_limiter
+            .as_ref()
+            .map(|limiter| limiter.queue_depth())
+            .unwrap_or(0)
+    }
+
     pub async fn auth(&self) -> Result<(), ClientError> {
         let body = serde_json::json!({
             "appId": self.config.app_id,
             "clientSecret": self.config.client_secret
         });
 
-        let response = self.client.post(QQ_AUTH_URL).json(&body).send().await?;
+        let response = self
+            .client
+            .post(self.config.auth_url())
+            .json(&body)
+            .send()
+            .await?;
 
         if response.status().is_client_error() || response.status().is_server_error() {
             let status = response.status();
@@ -72,6 +107,10 @@ impl QQClient {
         Ok(())
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     pub fn get_access_token(&self) -> Option<String> {
         self.token.read().ok().and_then(|lock| lock.clone())
     }
@@ -86,20 +125,26 @@ impl QQClient {
     pub async fn post_group_message(
         &self,
         group_openid: &str,
-        body: PostMessageBody,
+        mut body: PostMessageBody,
     ) -> Result<(), ClientError> {
-        let url = format!("{}/v2/groups/{}/messages", QQ_BASE_URL, group_openid);
-        self.send(&url, &body).await?;
+        if self.retry_policy.is_some() {
+            body.ensure_msg_seq();
+        }
+        self.send(Route::GroupMessage, group_openid, &body, true)
+            .await?;
         Ok(())
     }
 
     pub async fn post_c2c_message(
         &self,
         user_openid: &str,
-        body: PostMessageBody,
+        mut body: PostMessageBody,
     ) -> Result<(), ClientError> {
-        let url = format!("{}/v2/users/{}/messages", QQ_BASE_URL, user_openid);
-        self.send(&url, &body).await?;
+        if self.retry_policy.is_some() {
+            body.ensure_msg_seq();
+        }
+        self.send(Route::C2CMessage, user_openid, &body, true)
+            .await?;
         Ok(())
     }
 
@@ -108,17 +153,73 @@ impl QQClient {
         channel_id: &str,
         body: PostChannelMessageBody,
     ) -> Result<(), ClientError> {
-        let url = format!("{}/v2/channels/{}/messages", QQ_BASE_URL, channel_id);
-        self.send(&url, &body).await?;
+        self.send(Route::ChannelMessage, channel_id, &body, false)
+            .await?;
         Ok(())
     }
 
+    /// 被动回复一条消息，按消息来源发送到群聊、单聊或频道
+    ///
+    /// 超过被动回复有效期时不发送请求，直接返回 [`ClientError::PassiveReplyExpired`]。
+    pub async fn reply(
+        &self,
+        message: &IncomingMessage,
+        content: impl Into<String>,
+    ) -> Result<(), ClientError> {
+        let elapsed = message.received_at().elapsed();
+        let window = message.reply_window();
+        if elapsed >= window {
+            return Err(ClientError::PassiveReplyExpired { elapsed, window });
+        }
+        let content = content.into();
+        match message {
+            IncomingMessage::Group(m) => {
+                let body = PostMessageBody::from_msg_type(0)
+                    .with_content(content)
+                    .with_msg_id(m.id.clone());
+                self.post_group_message(&m.group_openid, body).await
+            }
+            IncomingMessage::C2C(m) => {
+                let body = PostMessageBody::from_msg_type(0)
+                    .with_content(content)
+                    .with_msg_id(m.id.clone());
+                self.post_c2c_message(&m.author.user_openid, body).await
+            }
+            IncomingMessage::Channel(m) => {
+                let body = PostChannelMessageBody {
+                    content,
+                    image: None,
+                    msg_id: Some(m.id.clone()),
+                    event_id: None,
+                };
+                self.post_channel_message(&m.channel_id, body).await
+            }
+        }
+    }
+
     pub async fn get_wss_endpoint(&self) -> Result<String, ClientError> {
+        #[derive(Deserialize)]
+        struct WssEndpoint {
+            url: String,
+        }
+        let endpoint = self.get::<WssEndpoint>("/gateway").await?;
+        debug!("WSS Endpoint: {:?}", endpoint.url);
+        Ok(endpoint.url)
+    }
+
+    /// 获取网关地址、建议分片数和 Session 创建限制
+    pub async fn get_gateway_bot(&self) -> Result<GatewayBot, ClientError> {
+        let gateway = self.get::<GatewayBot>("/gateway/bot").await?;
+        debug!("Gateway Bot: {:?}", gateway);
+        Ok(gateway)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ClientError> {
         let access_token = self
             .get_access_token()
             .ok_or_else(|| ClientError::Unknown("No access token available".to_string()))?;
 
-        let url = format!("{}/gateway", QQ_BASE_URL);
+        let url = format!("{}{}", self.config.api_base_url(), path);
         let response = self
             .client
             .get(url)
@@ -127,28 +228,62 @@ impl QQClient {
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            return Err(ClientError::GetWssEndpointFailed(format!(
-                "status: {}, response: {}",
-                status, text
-            )));
+            let err = ApiError::from_response(response).await;
+            error!("Failed to get {}: {}", path, err);
+            return Err(err.into());
         }
 
-        #[derive(Deserialize)]
-        struct WssEndpoint {
-            url: String,
-        }
-        let endpoint = response.json::<WssEndpoint>().await?;
-        debug!("WSS Endpoint: {:?}", endpoint.url);
-        Ok(endpoint.url)
+        Ok(response.json::<T>().await?)
     }
 
+    /// 发送请求，按重试策略处理失败
+    ///
+    /// `idempotent` 表示请求携带了 msg_seq，重复发送会被服务端去重。
     async fn send<T: Serialize + ?Sized>(
         &self,
-        url: &str,
+        route: Route,
+        target: &str,
+        body: &T,
+        idempotent: bool,
+    ) -> Result<(), ClientError> {
+        let mut attempt = 1;
+        loop {
+            let err = match self.send_once(route, target, body).await {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            let Some(policy) = &self.retry_policy else {
+                return Err(err);
+            };
+
+            // 之前的请求其实已经成功，服务端按 msg_seq 去重
+            if attempt > 1 && idempotent && err.api_kind() == Some(ApiErrorKind::Duplicated) {
+                debug!("重试请求被服务端去重，视为发送成功");
+                return Ok(());
+            }
+
+            if attempt >= policy.max_attempts || !policy.should_retry(&err, idempotent) {
+                return Err(err);
+            }
+
+            let delay = policy.delay(attempt);
+            warn!("请求失败（第 {} 次）: {}，{:?} 后重试", attempt, err, delay);
+            sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send_once<T: Serialize + ?Sized>(
+        &self,
+        route: Route,
+        target: &str,
         body: &T,
-    ) -> Result<Response, ClientError> {
+    ) -> Result<(), ClientError> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire(route, target).await;
+        }
+
+        let url = format!("{}{}", self.config.api_base_url(), route.path(target));
         let access_token = self
             .get_access_token()
             .ok_or_else(|| ClientError::Unknown("No access token available".to_string()))?;
@@ -162,16 +297,70 @@ impl QQClient {
             .await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            error!("Failed to post message: {}", text);
-            return Err(ClientError::PostMessageFailed(format!(
-                "status: {}, response: {}",
-                status, text
-            )));
+            let err = ApiError::from_response(response).await;
+            error!("Failed to post message: {}", err);
+            return Err(err.into());
         }
 
         debug!("Message posted successfully");
-        Ok(response)
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::models::{api_error::ApiErrorKind, message::PostMessageBody};
+    use crate::services::retry::RetryPolicy;
+    use crate::test_support::start_mock_api;
+
+    #[tokio::test]
+    async fn test_api_error_is_decoded() {
+        let (client, _api) = start_mock_api(vec![(
+            StatusCode::BAD_REQUEST,
+            json!({ "code": 22009, "message": "msg limit exceed", "err_code": 22009 }),
+        )])
+        .await;
+
+        let err = client
+            .post_group_message("group", PostMessageBody::from_msg_type(0))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.api_kind(), Some(ApiErrorKind::RateLimited));
+        let ClientError::Api(api_error) = err else {
+            panic!("expected api error");
+        };
+        assert_eq!(api_error.code, Some(22009));
+        assert_eq!(api_error.trace_id.as_deref(), Some("trace-123"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_reuses_msg_seq() {
+        let (client, api) = start_mock_api(vec![
+            (StatusCode::BAD_GATEWAY, json!({ "code": 500 })),
+            (StatusCode::BAD_REQUEST, json!({ "code": 40054005 })),
+        ])
+        .await;
+        let client = client.with_retry_policy(
+            RetryPolicy::new(3)
+                .with_base_delay(Duration::from_millis(10))
+                .with_jitter(0.0),
+        );
+
+        client
+            .post_group_message("group", PostMessageBody::from_msg_type(0))
+            .await
+            .unwrap();
+
+        let requests = api.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0]["msg_seq"].is_string());
+        assert_eq!(requests[0]["msg_seq"], requests[1]["msg_seq"]);
     }
 }
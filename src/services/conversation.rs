@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+use tracing::debug;
+
+use crate::{
+    models::{conversation_error::ConversationError, event::QQBotEvent, message::IncomingMessage},
+    services::{dispatcher::EventType, worker},
+};
+
+/// 多轮对话的参与方，同一个 key 的消息属于同一段对话
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConversationKey {
+    /// 群聊中的某个成员
+    Group {
+        group_openid: String,
+        member_openid: String,
+    },
+    /// 单聊用户
+    C2C { user_openid: String },
+    /// 子频道中的某个用户
+    Channel { channel_id: String, user_id: String },
+}
+
+impl ConversationKey {
+    /// 消息发送者所在的对话
+    pub fn of(message: &IncomingMessage) -> Self {
+        match message {
+            IncomingMessage::Group(m) => Self::Group {
+                group_openid: m.group_openid.clone(),
+                member_openid: m.author.member_openid.clone(),
+            },
+            IncomingMessage::C2C(m) => Self::C2C {
+                user_openid: m.author.user_openid.clone(),
+            },
+            IncomingMessage::Channel(m) => Self::Channel {
+                channel_id: m.channel_id.clone(),
+                user_id: m.author.id.clone(),
+            },
+        }
+    }
+
+    /// 从未解码的事件中取出对话，不是消息事件时返回 `None`
+    pub(crate) fn from_payload(payload: &QQBotEvent) -> Option<Self> {
+        let d = payload.d.as_ref()?;
+        let field = |value: &serde_json::Value| value.as_str().map(str::to_string);
+        let key = match EventType::from_str(payload.t.as_deref()?).ok()? {
+            EventType::GroupAtMessageCreate => Self::Group {
+                group_openid: field(&d["group_openid"])?,
+                member_openid: field(&d["author"]["member_openid"])?,
+            },
+            EventType::C2CMessageCreate => Self::C2C {
+                user_openid: field(&d["author"]["user_openid"])?,
+            },
+            EventType::AtMessageCreate => Self::Channel {
+                channel_id: field(&d["channel_id"])?,
+                user_id: field(&d["author"]["id"])?,
+            },
+            EventType::Ready | EventType::Resumed => return None,
+        };
+        Some(key)
+    }
+
+    /// 对话所在的会话：群、单聊用户或子频道，不区分群成员和频道用户
+    ///
+    /// 工作池按会话保证事件顺序，同一个群里不同成员的消息也按收到的顺序处理。
+    pub fn scope(&self) -> String {
+        match self {
+            Self::Group { group_openid, .. } => format!("group:{}", group_openid),
+            Self::C2C { user_openid } => format!("c2c:{}", user_openid),
+            Self::Channel { channel_id, .. } => format!("channel:{}", channel_id),
+        }
+    }
+}
+
+struct Waiter {
+    id: u64,
+    tx: oneshot::Sender<IncomingMessage>,
+}
+
+/// 多轮对话，处理函数可以等待同一用户的下一条消息，用于向导、确认等交互
+///
+/// 分发器收到的消息如果有处理函数在等待，经过中间件后交给等待方，不再交给事件处理器；
+/// 被黑名单等中间件拦截的消息不会交付，等待方继续等待。
+/// 克隆的实例共享同一份等待列表，通过 [`BotHandle::conversations`](super::handle::BotHandle::conversations)
+/// 获取。
+///
+/// ```ignore
+/// client.reply(&message, "确定要删除吗？(是/否)").await?;
+/// let answer = conversations.next_message_from(&message, Duration::from_secs(30)).await?;
+/// if answer.content().trim() == "是" { ... }
+/// ```
+#[derive(Clone, Default)]
+pub struct Conversations {
+    waiters: Arc<Mutex<HashMap<ConversationKey, Waiter>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Conversations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 等待对话中的下一条消息
+    ///
+    /// 同一对话同时只能有一个等待方；超时或等待被取消后，该对话的消息恢复正常处理。
+    ///
+    /// 在事件处理任务中调用时会让出工作池的名额，等待期间同一会话的其他事件照常处理，
+    /// 不会排在本次等待之后。
+    pub async fn next_message(
+        &self,
+        key: ConversationKey,
+        timeout: Duration,
+    ) -> Result<IncomingMessage, ConversationError> {
+        let (tx, mut rx) = oneshot::channel();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut waiters = self.lock();
+            if waiters.get(&key).is_some_and(|w| !w.tx.is_closed()) {
+                return Err(ConversationError::AlreadyWaiting);
+            }
+            waiters.insert(key.clone(), Waiter { id, tx });
+        }
+        let _guard = WaiterGuard {
+            conversations: self,
+            key,
+            id,
+        };
+        worker::release_current();
+
+        match tokio::time::timeout(timeout, &mut rx).await {
+            Ok(Ok(message)) => Ok(message),
+            // 超时的同时消息可能已经交付，不能丢弃
+            Ok(Err(_)) | Err(_) => rx
+                .try_recv()
+                .map_err(|_| ConversationError::Timeout(timeout)),
+        }
+    }
+
+    /// 等待 `message` 的发送者在同一对话中的下一条消息
+    pub async fn next_message_from(
+        &self,
+        message: &IncomingMessage,
+        timeout: Duration,
+    ) -> Result<IncomingMessage, ConversationError> {
+        self.next_message(ConversationKey::of(message), timeout)
+            .await
+    }
+
+    /// 是否有任何对话在等待消息
+    pub(crate) fn has_waiters(&self) -> bool {
+        !self.lock().is_empty()
+    }
+
+    /// 对话是否有处理函数在等待消息
+    pub fn is_waiting(&self, key: &ConversationKey) -> bool {
+        self.lock().get(key).is_some_and(|w| !w.tx.is_closed())
+    }
+
+    /// 取出对话的等待方，之后同一对话的消息不再交给它
+    pub(crate) fn claim(&self, key: &ConversationKey) -> Option<ClaimedWaiter> {
+        let waiter = self.lock().remove(key)?;
+        if waiter.tx.is_closed() {
+            return None;
+        }
+        Some(ClaimedWaiter {
+            key: key.clone(),
+            waiter,
+        })
+    }
+
+    /// 取出的等待方没有收到消息时放回，对话已有新的等待方时丢弃
+    pub(crate) fn restore(&self, claimed: ClaimedWaiter) {
+        if claimed.waiter.tx.is_closed() {
+            return;
+        }
+        self.lock().entry(claimed.key).or_insert(claimed.waiter);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<ConversationKey, Waiter>> {
+        self.waiters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 已从等待列表中取出、即将收到消息的等待方
+pub(crate) struct ClaimedWaiter {
+    key: ConversationKey,
+    waiter: Waiter,
+}
+
+impl ClaimedWaiter {
+    /// 交付消息，等待方已经超时或被取消时返回 `false`
+    pub(crate) fn send(self, message: IncomingMessage) -> bool {
+        debug!("消息交给等待中的对话: {:?}", self.key);
+        self.waiter.tx.send(message).is_ok()
+    }
+}
+
+/// 等待结束或被取消时移除自己的等待记录
+struct WaiterGuard<'a> {
+    conversations: &'a Conversations,
+    key: ConversationKey,
+    id: u64,
+}
+
+impl Drop for WaiterGuard<'_> {
+    fn drop(&mut self) {
+        let mut waiters = self.conversations.lock();
+        if waiters.get(&self.key).is_some_and(|w| w.id == self.id) {
+            waiters.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::event_client::QQEvent;
+    use crate::models::{
+        client_error::ClientError,
+        message::{C2CMessage, GroupMessage},
+    };
+    use crate::services::{
+        client::QQClient, dispatcher::Dispatcher, middleware::Blacklist, worker::WorkerPoolConfig,
+    };
+    use crate::test_support::{
+        ForwardingHandler, c2c_message_event, c2c_message_from, group_message_from, test_client,
+    };
+
+    /// 收到 `ask` 后等待同一发送者的下一条消息，其余消息按 ForwardingHandler 的格式转发
+    struct WizardHandler {
+        conversations: Conversations,
+        tx: mpsc::UnboundedSender<String>,
+    }
+
+    impl WizardHandler {
+        async fn handle(&self, message: IncomingMessage) {
+            if message.content().trim() != "ask" {
+                let _ = self.tx.send(format!("msg:{}", message.content()));
+                return;
+            }
+            let reply = match self
+                .conversations
+                .next_message_from(&message, Duration::from_secs(5))
+                .await
+            {
+                Ok(answer) => format!("answer:{}", answer.content()),
+                Err(e) => format!("error:{}", e),
+            };
+            let _ = self.tx.send(reply);
+        }
+    }
+
+    #[async_trait]
+    impl QQEvent for WizardHandler {
+        async fn on_group_at_message_create(
+            &self,
+            message: GroupMessage,
+            _client: &QQClient,
+        ) -> Result<(), ClientError> {
+            self.handle(IncomingMessage::Group(message)).await;
+            Ok(())
+        }
+
+        async fn on_c2c_message_create(
+            &self,
+            message: C2CMessage,
+            _client: &QQClient,
+        ) -> Result<(), ClientError> {
+            self.handle(IncomingMessage::C2C(message)).await;
+            Ok(())
+        }
+    }
+
+    async fn wait_until_waiting(conversations: &Conversations, key: &ConversationKey) {
+        while !conversations.is_waiting(key) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conversation_receives_next_message() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let conversations = Conversations::new();
+        let handler = WizardHandler {
+            conversations: conversations.clone(),
+            tx,
+        };
+        // 只有一个名额，等待中的处理函数必须让出名额其他事件才能处理
+        let dispatcher = Dispatcher::new(test_client(), Arc::new(handler))
+            .with_conversations(conversations.clone())
+            .with_worker_pool(WorkerPoolConfig::new(1));
+        let alice = ConversationKey::Group {
+            group_openid: "group_openid".into(),
+            member_openid: "alice".into(),
+        };
+
+        let next = async |rx: &mut mpsc::UnboundedReceiver<String>| {
+            tokio::time::timeout(Duration::from_secs(1), rx.recv())
+                .await
+                .expect("消息未处理")
+                .unwrap()
+        };
+
+        // 等待期间同一群的其他成员不受影响，不会排在等待之后
+        dispatcher.dispatch(group_message_from("alice", "1", "ask"));
+        wait_until_waiting(&conversations, &alice).await;
+        dispatcher.dispatch(group_message_from("bob", "2", "hello"));
+        assert_eq!(next(&mut rx).await, "msg:hello");
+        assert!(conversations.is_waiting(&alice));
+
+        dispatcher.dispatch(group_message_from("alice", "3", "yes"));
+        assert_eq!(next(&mut rx).await, "answer:yes");
+        dispatcher.dispatch(group_message_from("alice", "4", "after"));
+        assert_eq!(next(&mut rx).await, "msg:after");
+
+        dispatcher.tasks().close();
+        dispatcher.tasks().wait().await;
+        assert!(!conversations.is_waiting(&alice));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_conversation_timeout() {
+        let conversations = Conversations::new();
+        let key = ConversationKey::C2C {
+            user_openid: "user_openid".into(),
+        };
+
+        let waiting = tokio::spawn({
+            let conversations = conversations.clone();
+            let key = key.clone();
+            async move {
+                conversations
+                    .next_message(key, Duration::from_secs(30))
+                    .await
+            }
+        });
+        wait_until_waiting(&conversations, &key).await;
+        assert_eq!(
+            conversations
+                .next_message(key.clone(), Duration::from_secs(1))
+                .await
+                .unwrap_err(),
+            ConversationError::AlreadyWaiting
+        );
+
+        assert_eq!(
+            waiting.await.unwrap().unwrap_err(),
+            ConversationError::Timeout(Duration::from_secs(30))
+        );
+        assert!(!conversations.is_waiting(&key));
+
+        // 超时后消息恢复由事件处理器处理
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let dispatcher = Dispatcher::new(test_client(), Arc::new(ForwardingHandler(tx)))
+            .with_conversations(conversations);
+        dispatcher
+            .handle(c2c_message_event("1", "late"))
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), "c2c:late");
+    }
+
+    #[test]
+    fn test_conversation_scope_drops_member() {
+        let alice = ConversationKey::from_payload(&group_message_from("alice", "1", "hi")).unwrap();
+        let bob = ConversationKey::from_payload(&group_message_from("bob", "2", "hi")).unwrap();
+        assert_ne!(alice, bob);
+        assert_eq!(alice.scope(), "group:group_openid");
+        assert_eq!(alice.scope(), bob.scope());
+
+        let c2c = ConversationKey::from_payload(&c2c_message_from("carol", "3", "hi")).unwrap();
+        assert_eq!(c2c.scope(), "c2c:carol");
+    }
+
+    #[tokio::test]
+    async fn test_conversation_reply_passes_middleware() {
+        let conversations = Conversations::new();
+        let key = ConversationKey::C2C {
+            user_openid: "user_openid".into(),
+        };
+        let waiting = tokio::spawn({
+            let conversations = conversations.clone();
+            let key = key.clone();
+            async move {
+                conversations
+                    .next_message(key, Duration::from_secs(5))
+                    .await
+            }
+        });
+        wait_until_waiting(&conversations, &key).await;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let blacklist = Blacklist::new(["user_openid"]);
+        let dispatcher = Dispatcher::new(test_client(), Arc::new(ForwardingHandler(tx)))
+            .with_conversations(conversations.clone())
+            .with_middleware(Arc::new(blacklist.clone()));
+
+        // 黑名单中的用户回复对话：消息被拦截，等待方继续等待
+        dispatcher.dispatch(c2c_message_event("1", "blocked"));
+        dispatcher.tasks().close();
+        dispatcher.tasks().wait().await;
+        dispatcher.tasks().reopen();
+        assert!(conversations.is_waiting(&key));
+        assert!(!waiting.is_finished());
+
+        assert!(blacklist.remove("user_openid"));
+        dispatcher
+            .handle(c2c_message_event("2", "allowed"))
+            .await
+            .unwrap();
+        assert_eq!(waiting.await.unwrap().unwrap().content(), "allowed");
+        assert!(rx.try_recv().is_err());
+    }
+}
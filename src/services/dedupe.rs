@@ -0,0 +1,119 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+
+/// 默认的去重时间窗口
+const DEFAULT_WINDOW: Duration = Duration::from_secs(300);
+/// 默认最多记录的事件 id 数
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// 事件去重存储，按事件 id 判断事件是否已经处理过
+///
+/// WebHook 重试和网关 Resume 都可能重复投递同一个事件；多实例部署时可以实现
+/// 基于共享存储的版本。
+#[async_trait]
+pub trait DedupeStore: Send + Sync {
+    /// 记录事件 id，首次出现返回 `true`，时间窗口内重复出现返回 `false`
+    async fn insert(&self, event_id: &str) -> bool;
+}
+
+/// 内存中的去重存储，只保留时间窗口内且不超过容量的事件 id
+pub struct MemoryDedupeStore {
+    window: Duration,
+    capacity: usize,
+    seen: Mutex<Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    ids: HashSet<String>,
+    /// 按记录时间排序，用于淘汰最早的 id，与 `ids` 一一对应
+    order: VecDeque<(String, Instant)>,
+}
+
+impl MemoryDedupeStore {
+    pub fn new(window: Duration, capacity: usize) -> Self {
+        Self {
+            window,
+            capacity: capacity.max(1),
+            seen: Default::default(),
+        }
+    }
+
+    /// 当前记录的事件 id 数
+    pub fn len(&self) -> usize {
+        self.seen
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .ids
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for MemoryDedupeStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW, DEFAULT_CAPACITY)
+    }
+}
+
+#[async_trait]
+impl DedupeStore for MemoryDedupeStore {
+    async fn insert(&self, event_id: &str) -> bool {
+        let now = Instant::now();
+        let mut guard = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let seen = &mut *guard;
+
+        // 淘汰过期的 id
+        while let Some((_, at)) = seen.order.front() {
+            if now.duration_since(*at) < self.window {
+                break;
+            }
+            if let Some((id, _)) = seen.order.pop_front() {
+                seen.ids.remove(&id);
+            }
+        }
+        if seen.ids.contains(event_id) {
+            return false;
+        }
+
+        // 超出容量时淘汰最早的 id
+        while seen.order.len() >= self.capacity {
+            if let Some((id, _)) = seen.order.pop_front() {
+                seen.ids.remove(&id);
+            }
+        }
+        seen.ids.insert(event_id.to_string());
+        seen.order.push_back((event_id.to_string(), now));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_memory_dedupe_window_and_capacity() {
+        let store = MemoryDedupeStore::new(Duration::from_secs(60), 2);
+        assert!(store.insert("a").await);
+        assert!(!store.insert("a").await);
+
+        // 超出时间窗口后视为新事件
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(store.insert("a").await);
+
+        // 超出容量时淘汰最早的 id
+        assert!(store.insert("b").await);
+        assert!(store.insert("c").await);
+        assert_eq!(store.len(), 2);
+        assert!(store.insert("a").await);
+        assert!(!store.insert("c").await);
+    }
+}
@@ -0,0 +1,476 @@
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures_util::FutureExt;
+use strum::{EnumString, IntoStaticStr};
+use tokio::time::Instant;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, error};
+
+use crate::{
+    event_client::QQEvent,
+    models::{
+        client_error::ClientError,
+        error::AppError,
+        event::QQBotEvent,
+        handler_error::HandlerError,
+        message::{C2CMessage, ChannelMessage, GroupMessage, IncomingMessage},
+    },
+    services::{
+        client::QQClient,
+        conversation::{ClaimedWaiter, ConversationKey, Conversations},
+        dedupe::{DedupeStore, MemoryDedupeStore},
+        middleware::{Middleware, Next},
+        worker::{WorkerPool, WorkerPoolConfig},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, IntoStaticStr)]
+pub enum EventType {
+    #[strum(serialize = "GROUP_AT_MESSAGE_CREATE")]
+    GroupAtMessageCreate,
+    #[strum(serialize = "READY")]
+    Ready,
+    #[strum(serialize = "RESUMED")]
+    Resumed,
+    #[strum(serialize = "C2C_MESSAGE_CREATE")]
+    C2CMessageCreate,
+    #[strum(serialize = "AT_MESSAGE_CREATE")]
+    AtMessageCreate,
+}
+
+/// 会交给事件处理器的消息事件类型，即 [`Event::kind`] 的返回值
+///
+/// 不包含 `READY`、`RESUMED` 等连接生命周期事件，它们由网关连接自己处理，不会分发。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, IntoStaticStr)]
+pub enum MessageKind {
+    #[strum(serialize = "GROUP_AT_MESSAGE_CREATE")]
+    GroupAtMessageCreate,
+    #[strum(serialize = "C2C_MESSAGE_CREATE")]
+    C2CMessageCreate,
+    #[strum(serialize = "AT_MESSAGE_CREATE")]
+    AtMessageCreate,
+}
+
+impl From<MessageKind> for EventType {
+    fn from(kind: MessageKind) -> Self {
+        match kind {
+            MessageKind::GroupAtMessageCreate => Self::GroupAtMessageCreate,
+            MessageKind::C2CMessageCreate => Self::C2CMessageCreate,
+            MessageKind::AtMessageCreate => Self::AtMessageCreate,
+        }
+    }
+}
+
+/// 解码后交给事件处理器的事件
+#[derive(Debug, Clone)]
+pub enum Event {
+    GroupAtMessageCreate(GroupMessage),
+    C2CMessageCreate(C2CMessage),
+    AtMessageCreate(ChannelMessage),
+}
+
+impl Event {
+    /// 解码 Dispatch 事件，连接生命周期事件和未知类型返回 `None`
+    pub fn decode(payload: QQBotEvent) -> Result<Option<Self>, serde_json::Error> {
+        let Some(t) = payload.t.as_deref() else {
+            return Ok(None);
+        };
+        let Ok(ty) = EventType::from_str(t) else {
+            debug!("忽略未处理的事件类型: {}", t);
+            return Ok(None);
+        };
+
+        let d = payload.d.unwrap_or_default();
+        let event = match ty {
+            EventType::GroupAtMessageCreate => {
+                Self::GroupAtMessageCreate(serde_json::from_value(d)?)
+            }
+            EventType::C2CMessageCreate => Self::C2CMessageCreate(serde_json::from_value(d)?),
+            EventType::AtMessageCreate => Self::AtMessageCreate(serde_json::from_value(d)?),
+            EventType::Ready | EventType::Resumed => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Self::GroupAtMessageCreate(_) => MessageKind::GroupAtMessageCreate,
+            Self::C2CMessageCreate(_) => MessageKind::C2CMessageCreate,
+            Self::AtMessageCreate(_) => MessageKind::AtMessageCreate,
+        }
+    }
+
+    /// 设置消息的接收时间，被动回复的有效期从这里开始计算
+    fn set_received_at(&mut self, received_at: Instant) {
+        match self {
+            Self::GroupAtMessageCreate(m) => m.received_at = received_at,
+            Self::C2CMessageCreate(m) => m.received_at = received_at,
+            Self::AtMessageCreate(m) => m.received_at = received_at,
+        }
+    }
+
+    /// 调用事件处理器中对应的方法
+    pub async fn call(self, handler: &dyn QQEvent, client: &QQClient) -> Result<(), ClientError> {
+        match self {
+            Self::GroupAtMessageCreate(message) => {
+                handler.on_group_at_message_create(message, client).await
+            }
+            Self::C2CMessageCreate(message) => handler.on_c2c_message_create(message, client).await,
+            Self::AtMessageCreate(message) => handler.on_at_message_create(message, client).await,
+        }
+    }
+
+    /// 用户消息事件对应的 [`IncomingMessage`]
+    pub fn message(&self) -> Option<IncomingMessage> {
+        match self {
+            Self::GroupAtMessageCreate(m) => Some(IncomingMessage::Group(m.clone())),
+            Self::C2CMessageCreate(m) => Some(IncomingMessage::C2C(m.clone())),
+            Self::AtMessageCreate(m) => Some(IncomingMessage::Channel(m.clone())),
+        }
+    }
+}
+
+impl From<IncomingMessage> for Event {
+    fn from(message: IncomingMessage) -> Self {
+        match message {
+            IncomingMessage::Group(m) => Self::GroupAtMessageCreate(m),
+            IncomingMessage::C2C(m) => Self::C2CMessageCreate(m),
+            IncomingMessage::Channel(m) => Self::AtMessageCreate(m),
+        }
+    }
+}
+
+/// 事件分发的统计数据
+#[derive(Debug, Default)]
+pub struct DispatchMetrics {
+    duplicates_dropped: AtomicU64,
+}
+
+impl DispatchMetrics {
+    /// 因事件 id 重复而丢弃的事件数
+    pub fn duplicates_dropped(&self) -> u64 {
+        self.duplicates_dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// 事件分发器，WebHook 和 WebSocket 收到的 Dispatch 事件都经由这里交给事件处理器
+///
+/// 事件交给工作池在后台处理，不阻塞接收：同时处理的事件数有上限，同一会话的事件按收到的
+/// 顺序处理。任务由 [`TaskTracker`] 跟踪，关闭时可以等待处理中的事件完成。带 id 的事件默认经过 [`MemoryDedupeStore`] 去重，
+/// 解码后依次经过注册的 [`Middleware`] 再交给事件处理器。处理器 panic 时转换为
+/// [`HandlerError::Panicked`]，与其他错误一起交给 [`QQEvent::on_error`]。
+#[derive(Clone)]
+pub struct Dispatcher {
+    client: QQClient,
+    event_handler: Arc<dyn QQEvent>,
+    dedupe: Option<Arc<dyn DedupeStore>>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    metrics: Arc<DispatchMetrics>,
+    tasks: TaskTracker,
+    pool: Arc<WorkerPool>,
+    conversations: Conversations,
+}
+
+impl Dispatcher {
+    pub fn new(client: QQClient, event_handler: Arc<dyn QQEvent>) -> Self {
+        let tasks = TaskTracker::new();
+        Self {
+            client,
+            event_handler,
+            dedupe: Some(Arc::new(MemoryDedupeStore::default())),
+            middlewares: Vec::new(),
+            metrics: Default::default(),
+            pool: Arc::new(WorkerPool::new(Default::default(), tasks.clone())),
+            tasks,
+            conversations: Conversations::new(),
+        }
+    }
+
+    /// 设置并发上限和是否按会话顺序处理，默认最多同时处理 64 个事件并保持会话内顺序
+    pub fn with_worker_pool(mut self, config: WorkerPoolConfig) -> Self {
+        self.pool = Arc::new(WorkerPool::new(config, self.tasks.clone()));
+        self
+    }
+
+    /// 使用自定义的去重存储
+    pub fn with_dedupe_store(mut self, store: Arc<dyn DedupeStore>) -> Self {
+        self.dedupe = Some(store);
+        self
+    }
+
+    /// 关闭事件去重
+    pub fn without_dedupe(mut self) -> Self {
+        self.dedupe = None;
+        self
+    }
+
+    /// 添加中间件，先添加的在外层
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// 使用共享的多轮对话，例如由 [`BotHandle`](super::handle::BotHandle) 持有的实例
+    pub fn with_conversations(mut self, conversations: Conversations) -> Self {
+        self.conversations = conversations;
+        self
+    }
+
+    pub fn conversations(&self) -> &Conversations {
+        &self.conversations
+    }
+
+    /// 使用共享的统计数据，例如由 [`BotHandle`](super::handle::BotHandle) 持有的实例
+    pub fn with_metrics(mut self, metrics: Arc<DispatchMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn metrics(&self) -> &DispatchMetrics {
+        &self.metrics
+    }
+
+    /// 把一个 Dispatch 事件交给工作池在后台处理
+    pub fn dispatch(&self, payload: QQBotEvent) {
+        // 在工作池中排队的时间也计入被动回复的有效期
+        let received_at = Instant::now();
+        let conversation = ConversationKey::from_payload(&payload);
+        let key = conversation.as_ref().map(ConversationKey::scope);
+        // 在收到时就取出等待方：消息不经过工作池，否则会排在发起等待的事件之后；
+        // 之后的消息则照常排队，保持顺序
+        let claimed = conversation
+            .filter(|_| self.conversations.has_waiters())
+            .and_then(|conversation| self.conversations.claim(&conversation));
+        let awaited = claimed.is_some();
+        let dispatcher = self.clone();
+        let job = async move {
+            if let Err(e) = dispatcher
+                .handle_received(payload, received_at, claimed)
+                .await
+            {
+                error!("Error handling dispatch event: {:?}", e);
+            }
+        };
+        if awaited {
+            self.tasks.spawn(job);
+        } else {
+            self.pool.submit(key, job);
+        }
+    }
+
+    /// 等待空闲名额或等待同一会话前序事件的事件数
+    pub fn queued(&self) -> usize {
+        self.pool.queued()
+    }
+
+    /// 去重、解码事件并经过中间件调用事件处理器，处理完成后返回
+    ///
+    /// 有处理函数在等待该对话的消息时，消息通过中间件后交给等待方。
+    pub async fn handle(&self, payload: QQBotEvent) -> Result<(), AppError> {
+        self.handle_received(payload, Instant::now(), None).await
+    }
+
+    async fn handle_received(
+        &self,
+        payload: QQBotEvent,
+        received_at: Instant,
+        claimed: Option<ClaimedWaiter>,
+    ) -> Result<(), AppError> {
+        if let Some(t) = &payload.t {
+            debug!("Event Type: {}", t);
+        }
+        if let (Some(store), Some(id)) = (&self.dedupe, payload.id.as_deref())
+            && !store.insert(id).await
+        {
+            debug!("丢弃重复事件: {}", id);
+            self.metrics
+                .duplicates_dropped
+                .fetch_add(1, Ordering::Relaxed);
+            if let Some(claimed) = claimed {
+                self.conversations.restore(claimed);
+            }
+            return Ok(());
+        }
+        let mut event = match Event::decode(payload) {
+            Ok(Some(event)) => event,
+            decoded => {
+                if let Some(claimed) = claimed {
+                    self.conversations.restore(claimed);
+                }
+                decoded?;
+                return Ok(());
+            }
+        };
+        event.set_received_at(received_at);
+
+        // 等待方在中间件链末尾收到消息，黑名单、冷却等中间件同样作用于对话中的回复
+        let claimed = claimed.or_else(|| {
+            let message = event.message()?;
+            self.conversations
+                .has_waiters()
+                .then(|| self.conversations.claim(&ConversationKey::of(&message)))
+                .flatten()
+        });
+        let waiter = Mutex::new(claimed);
+
+        let snapshot = event.clone();
+        let result = AssertUnwindSafe(
+            Next::new(&self.middlewares, &*self.event_handler, &self.client)
+                .with_waiter(&waiter)
+                .run(event),
+        )
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|panic| Err(HandlerError::Panicked(panic_message(&*panic))));
+        // 消息被中间件拦截，等待方继续等待下一条消息
+        if let Some(claimed) = waiter.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            self.conversations.restore(claimed);
+        }
+
+        if let Err(e) = &result {
+            let on_error = self.event_handler.on_error(&snapshot, e, &self.client);
+            if let Err(panic) = AssertUnwindSafe(on_error).catch_unwind().await {
+                error!("on_error panicked: {}", panic_message(&*panic));
+            }
+        }
+        Ok(result?)
+    }
+
+    /// 处理中的事件任务
+    pub(crate) fn tasks(&self) -> &TaskTracker {
+        &self.tasks
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::test_support::{
+        ForwardingHandler, c2c_message_event, group_message_event, test_client,
+    };
+
+    #[tokio::test]
+    async fn test_dispatcher_decodes_and_calls_handler() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let dispatcher = Dispatcher::new(test_client(), Arc::new(ForwardingHandler(tx)));
+
+        dispatcher
+            .handle(group_message_event("1", "hello"))
+            .await
+            .unwrap();
+        dispatcher
+            .handle(c2c_message_event("2", "hi"))
+            .await
+            .unwrap();
+        // 未处理的事件类型直接忽略
+        let unknown = QQBotEvent {
+            op: 0,
+            t: Some("GROUP_ADD_ROBOT".to_string()),
+            ..Default::default()
+        };
+        dispatcher.handle(unknown).await.unwrap();
+
+        assert_eq!(rx.recv().await.unwrap(), "group:hello");
+        assert_eq!(rx.recv().await.unwrap(), "c2c:hi");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher_drops_duplicate_events() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let dispatcher = Dispatcher::new(test_client(), Arc::new(ForwardingHandler(tx)));
+
+        // 同一事件经 WebHook 重试和网关 Resume 各投递一次
+        dispatcher
+            .handle(group_message_event("1", "once"))
+            .await
+            .unwrap();
+        dispatcher
+            .handle(group_message_event("1", "once"))
+            .await
+            .unwrap();
+
+        assert_eq!(rx.recv().await.unwrap(), "group:once");
+        assert!(rx.try_recv().is_err());
+        assert_eq!(dispatcher.metrics().duplicates_dropped(), 1);
+    }
+
+    /// 群消息 panic、单聊返回错误，并记录交给 on_error 的错误
+    struct FaultyHandler(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl QQEvent for FaultyHandler {
+        async fn on_group_at_message_create(
+            &self,
+            message: GroupMessage,
+            _client: &QQClient,
+        ) -> Result<(), ClientError> {
+            panic!("boom: {}", message.content);
+        }
+
+        async fn on_c2c_message_create(
+            &self,
+            _message: C2CMessage,
+            _client: &QQClient,
+        ) -> Result<(), ClientError> {
+            Err(ClientError::Unknown("failed".into()))
+        }
+
+        async fn on_error(&self, event: &Event, error: &HandlerError, _client: &QQClient) {
+            let content = event.message().unwrap().content().to_string();
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{:?}/{}: {}", event.kind(), content, error));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic_is_reported() {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let dispatcher = Dispatcher::new(test_client(), Arc::new(FaultyHandler(errors.clone())));
+
+        let err = dispatcher
+            .handle(group_message_event("1", "hi"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::HandlerError(HandlerError::Panicked(ref m)) if m == "boom: hi"
+        ));
+
+        // 后台任务中的 panic 同样被捕获
+        dispatcher.dispatch(group_message_event("2", "again"));
+        dispatcher.dispatch(c2c_message_event("3", "hello"));
+        dispatcher.tasks().close();
+        dispatcher.tasks().wait().await;
+
+        let mut errors = errors.lock().unwrap().clone();
+        errors[1..].sort();
+        assert_eq!(
+            errors,
+            vec![
+                "GroupAtMessageCreate/hi: Handler panicked: boom: hi",
+                "C2CMessageCreate/hello: Client error: Unknown error: failed",
+                "GroupAtMessageCreate/again: Handler panicked: boom: again",
+            ]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use crate::services::conversation::Conversations;
+use crate::services::dispatcher::DispatchMetrics;
+use crate::services::websocket::ShardManager;
+use crate::services::websocket::command::GatewaySender;
+use crate::services::websocket::state::ConnectionState;
+
+/// 运行中机器人的控制句柄，通过 [`ServerBuilder::handle`](super::server::ServerBuilder::handle) 获取
+///
+/// 句柄可以自由克隆并传递到其他任务中。
+#[derive(Clone)]
+pub struct BotHandle {
+    shutdown: CancellationToken,
+    shards: Arc<OnceLock<ShardManager>>,
+    metrics: Arc<DispatchMetrics>,
+    conversations: Conversations,
+}
+
+impl BotHandle {
+    pub(crate) fn new(
+        shutdown: CancellationToken,
+        shards: Arc<OnceLock<ShardManager>>,
+        metrics: Arc<DispatchMetrics>,
+        conversations: Conversations,
+    ) -> Self {
+        Self {
+            shutdown,
+            shards,
+            metrics,
+            conversations,
+        }
+    }
+
+    /// 多轮对话，可以在事件处理器中等待用户的下一条消息
+    pub fn conversations(&self) -> &Conversations {
+        &self.conversations
+    }
+
+    /// 事件分发的统计数据
+    pub fn metrics(&self) -> &DispatchMetrics {
+        &self.metrics
+    }
+
+    /// 请求优雅关闭
+    ///
+    /// 各分片发送 Close 帧断开网关连接，WebHook 服务停止接收新请求，
+    /// 然后在 `timing.shutdown_timeout_secs` 内等待处理中的事件完成。
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// 是否已经请求关闭
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    /// 等待直到请求关闭
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.cancelled().await;
+    }
+
+    /// 订阅某个分片的连接状态，网关分片启动前返回 `None`
+    pub fn connection_state(&self, shard_id: u32) -> Option<watch::Receiver<ConnectionState>> {
+        self.shards.get()?.subscribe(shard_id)
+    }
+
+    /// 获取某个分片的网关指令发送端，网关分片启动前返回 `None`
+    pub fn gateway(&self, shard_id: u32) -> Option<GatewaySender> {
+        self.shards.get()?.command_sender(shard_id)
+    }
+
+    /// 本进程所有分片当前的连接状态，网关分片启动前为空
+    pub fn connection_states(&self) -> Vec<ConnectionState> {
+        self.shards
+            .get()
+            .map(ShardManager::connection_states)
+            .unwrap_or_default()
+    }
+
+    /// 所有分片平均心跳 RTT 的平均值，尚未收到任何心跳 ACK 时返回 `None`
+    pub fn latency(&self) -> Option<Duration> {
+        let samples: Vec<Duration> = self
+            .connection_states()
+            .iter()
+            .filter_map(|state| state.heartbeat_rtt_avg)
+            .collect();
+        if samples.is_empty() {
+            return None;
+        }
+        Some(samples.iter().sum::<Duration>() / samples.len() as u32)
+    }
+}
@@ -0,0 +1,560 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::{
+    event_client::QQEvent,
+    models::handler_error::HandlerError,
+    services::{
+        client::QQClient,
+        conversation::ClaimedWaiter,
+        dispatcher::{Event, MessageKind},
+    },
+};
+
+/// 冷却记录超过该数量时清理已过期的记录
+const COOLDOWN_PRUNE_THRESHOLD: usize = 1024;
+
+/// 包裹事件处理的中间件
+///
+/// 中间件按注册顺序执行，先注册的在外层。调用 `next.run(event)` 把事件交给后面的中间件
+/// 和事件处理器；不调用即短路，事件不会到达处理器。中间件可以在传递前修改事件，
+/// 也可以检查或替换返回的错误。
+///
+/// ```ignore
+/// struct TrimContent;
+///
+/// #[async_trait]
+/// impl Middleware for TrimContent {
+///     async fn handle(&self, mut event: Event, client: &QQClient, next: Next<'_>) -> Result<(), HandlerError> {
+///         if let Event::C2CMessageCreate(m) = &mut event {
+///             m.content = m.content.trim().to_string();
+///         }
+///         next.run(event).await
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    async fn handle(
+        &self,
+        event: Event,
+        client: &QQClient,
+        next: Next<'_>,
+    ) -> Result<(), HandlerError>;
+}
+
+/// 中间件链中剩余的部分
+pub struct Next<'a> {
+    chain: &'a [Arc<dyn Middleware>],
+    handler: &'a dyn QQEvent,
+    client: &'a QQClient,
+    /// 等待该消息的多轮对话，消息通过所有中间件后交给它而不是事件处理器
+    waiter: Option<&'a Mutex<Option<ClaimedWaiter>>>,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        chain: &'a [Arc<dyn Middleware>],
+        handler: &'a dyn QQEvent,
+        client: &'a QQClient,
+    ) -> Self {
+        Self {
+            chain,
+            handler,
+            client,
+            waiter: None,
+        }
+    }
+
+    /// 消息通过中间件链后交给等待方；被中间件拦截时等待方仍留在 `waiter` 中
+    pub(crate) fn with_waiter(mut self, waiter: &'a Mutex<Option<ClaimedWaiter>>) -> Self {
+        self.waiter = Some(waiter);
+        self
+    }
+
+    /// 调用下一个中间件，已经是最后一个时调用事件处理器
+    pub async fn run(self, event: Event) -> Result<(), HandlerError> {
+        match self.chain.split_first() {
+            Some((middleware, chain)) => {
+                let next = Next { chain, ..self };
+                middleware.handle(event, self.client, next).await
+            }
+            None => {
+                let claimed = self
+                    .waiter
+                    .and_then(|waiter| waiter.lock().unwrap_or_else(|e| e.into_inner()).take());
+                if let (Some(claimed), Some(message)) = (claimed, event.message())
+                    && claimed.send(message)
+                {
+                    return Ok(());
+                }
+                Ok(event.call(self.handler, self.client).await?)
+            }
+        }
+    }
+}
+
+/// 记录每个事件的类型、发送者、耗时和错误
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LoggingMiddleware;
+
+#[async_trait]
+impl Middleware for LoggingMiddleware {
+    async fn handle(
+        &self,
+        event: Event,
+        _client: &QQClient,
+        next: Next<'_>,
+    ) -> Result<(), HandlerError> {
+        let kind = event.kind();
+        let sender = event
+            .message()
+            .map(|m| m.sender_id().to_string())
+            .unwrap_or_default();
+        let started = Instant::now();
+        let result = next.run(event).await;
+        match &result {
+            Ok(()) => info!(
+                "{:?} from {} 处理完成，耗时 {:?}",
+                kind,
+                sender,
+                started.elapsed()
+            ),
+            Err(e) => warn!(
+                "{:?} from {} 处理失败，耗时 {:?}: {}",
+                kind,
+                sender,
+                started.elapsed(),
+                e
+            ),
+        }
+        result
+    }
+}
+
+/// 按发送者限制处理频率，冷却期内的消息不会到达处理器
+pub struct Cooldown {
+    period: Duration,
+    reply: Option<String>,
+    last: Mutex<HashMap<String, Instant>>,
+}
+
+impl Cooldown {
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            reply: None,
+            last: Default::default(),
+        }
+    }
+
+    /// 冷却期内被动回复提示，未设置时静默丢弃
+    pub fn with_reply(mut self, reply: impl Into<String>) -> Self {
+        self.reply = Some(reply.into());
+        self
+    }
+
+    /// 记录一次处理，冷却期内返回 `false`
+    fn acquire(&self, sender: &str) -> bool {
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(at) = last.get(sender)
+            && now.duration_since(*at) < self.period
+        {
+            return false;
+        }
+        if last.len() >= COOLDOWN_PRUNE_THRESHOLD {
+            last.retain(|_, at| now.duration_since(*at) < self.period);
+        }
+        last.insert(sender.to_string(), now);
+        true
+    }
+}
+
+#[async_trait]
+impl Middleware for Cooldown {
+    async fn handle(
+        &self,
+        event: Event,
+        client: &QQClient,
+        next: Next<'_>,
+    ) -> Result<(), HandlerError> {
+        let Some(message) = event.message() else {
+            return next.run(event).await;
+        };
+        if self.acquire(message.sender_id()) {
+            return next.run(event).await;
+        }
+        info!("{} 处于冷却期，忽略消息", message.sender_id());
+        if let Some(reply) = &self.reply {
+            client.reply(&message, reply.clone()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// 屏蔽指定发送者或群的消息
+///
+/// 克隆的实例共享同一份名单，注册为中间件后仍可以在其他任务中增删。
+#[derive(Clone, Default)]
+pub struct Blacklist {
+    ids: Arc<RwLock<HashSet<String>>>,
+}
+
+impl Blacklist {
+    /// 发送者 openid、频道用户 id 或 group_openid
+    pub fn new<I, S>(ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            ids: Arc::new(RwLock::new(ids.into_iter().map(Into::into).collect())),
+        }
+    }
+
+    pub fn insert(&self, id: impl Into<String>) -> bool {
+        self.ids
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id.into())
+    }
+
+    pub fn remove(&self, id: &str) -> bool {
+        self.ids
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(id)
+    }
+}
+
+#[async_trait]
+impl Middleware for Blacklist {
+    async fn handle(
+        &self,
+        event: Event,
+        _client: &QQClient,
+        next: Next<'_>,
+    ) -> Result<(), HandlerError> {
+        let blocked = match &event {
+            Event::GroupAtMessageCreate(m) => {
+                self.contains(&m.author.member_openid) || self.contains(&m.group_openid)
+            }
+            Event::C2CMessageCreate(m) => self.contains(&m.author.user_openid),
+            Event::AtMessageCreate(m) => self.contains(&m.author.id),
+        };
+        if blocked {
+            info!("忽略黑名单中的消息: {:?}", event.kind());
+            return Ok(());
+        }
+        next.run(event).await
+    }
+}
+
+/// 限制事件处理的时长，超时后取消处理并返回 [`HandlerError::Timeout`]
+///
+/// 设置了兜底回复时，如果消息仍在被动回复有效期内，超时后回复用户。应放在中间件链的外层，
+/// 使内层中间件的耗时也计算在内。
+pub struct Timeout {
+    default: Duration,
+    events: HashMap<MessageKind, Duration>,
+    fallback: Option<String>,
+}
+
+impl Timeout {
+    pub fn new(default: Duration) -> Self {
+        Self {
+            default,
+            events: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// 为某类事件单独设置超时时间
+    pub fn with_event(mut self, kind: MessageKind, timeout: Duration) -> Self {
+        self.events.insert(kind, timeout);
+        self
+    }
+
+    /// 超时后发送的兜底回复
+    pub fn with_fallback_reply(mut self, reply: impl Into<String>) -> Self {
+        self.fallback = Some(reply.into());
+        self
+    }
+
+    fn timeout(&self, kind: MessageKind) -> Duration {
+        self.events.get(&kind).copied().unwrap_or(self.default)
+    }
+}
+
+#[async_trait]
+impl Middleware for Timeout {
+    async fn handle(
+        &self,
+        event: Event,
+        client: &QQClient,
+        next: Next<'_>,
+    ) -> Result<(), HandlerError> {
+        let timeout = self.timeout(event.kind());
+        let message = event.message();
+        let Ok(result) = tokio::time::timeout(timeout, next.run(event)).await else {
+            warn!("事件处理超过 {:?}，已取消", timeout);
+            if let (Some(fallback), Some(message)) = (&self.fallback, message) {
+                if message.reply_remaining().is_some() {
+                    client.reply(&message, fallback.clone()).await?;
+                } else {
+                    warn!("被动回复已过期，不发送兜底回复");
+                }
+            }
+            return Err(HandlerError::Timeout(timeout));
+        };
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::models::{
+        client_error::ClientError,
+        command_error::ArgError,
+        error::AppError,
+        message::{GROUP_REPLY_WINDOW, GroupMessage},
+    };
+    use crate::services::dispatcher::Dispatcher;
+    use crate::test_support::{
+        ForwardingHandler, c2c_message_event, group_message_event, start_mock_api, test_client,
+    };
+
+    /// 记录经过的顺序，并吞掉内层返回的错误
+    struct Recorder(&'static str, Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl Middleware for Recorder {
+        async fn handle(
+            &self,
+            event: Event,
+            _client: &QQClient,
+            next: Next<'_>,
+        ) -> Result<(), HandlerError> {
+            self.1.lock().unwrap().push(format!("before:{}", self.0));
+            let result = next.run(event).await;
+            let outcome = if result.is_ok() { "ok" } else { "err" };
+            self.1
+                .lock()
+                .unwrap()
+                .push(format!("after:{}:{}", self.0, outcome));
+            Ok(())
+        }
+    }
+
+    /// 把单聊内容改为大写，`stop` 短路，`fail` 返回错误
+    struct Rewrite;
+
+    #[async_trait]
+    impl Middleware for Rewrite {
+        async fn handle(
+            &self,
+            mut event: Event,
+            _client: &QQClient,
+            next: Next<'_>,
+        ) -> Result<(), HandlerError> {
+            if let Event::C2CMessageCreate(m) = &mut event {
+                match m.content.as_str() {
+                    "stop" => return Ok(()),
+                    "fail" => return Err(ArgError::Missing(1).into()),
+                    _ => m.content = m.content.to_uppercase(),
+                }
+            }
+            next.run(event).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware_chain() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let dispatcher = Dispatcher::new(test_client(), Arc::new(ForwardingHandler(tx)))
+            .without_dedupe()
+            .with_middleware(Arc::new(Recorder("outer", log.clone())))
+            .with_middleware(Arc::new(Recorder("inner", log.clone())))
+            .with_middleware(Arc::new(Rewrite));
+
+        for (id, content) in [("1", "hi"), ("2", "stop"), ("3", "fail")] {
+            dispatcher
+                .handle(c2c_message_event(id, content))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(rx.recv().await.unwrap(), "c2c:HI");
+        assert!(rx.try_recv().is_err());
+        // inner 吞掉了错误，outer 看到的是成功
+        let expected: Vec<String> = [
+            "before:outer",
+            "before:inner",
+            "after:inner:ok",
+            "after:outer:ok",
+            "before:outer",
+            "before:inner",
+            "after:inner:ok",
+            "after:outer:ok",
+            "before:outer",
+            "before:inner",
+            "after:inner:err",
+            "after:outer:ok",
+        ]
+        .map(String::from)
+        .into();
+        assert_eq!(*log.lock().unwrap(), expected);
+
+        // 没有中间件处理时，错误返回给调用方
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let dispatcher = Dispatcher::new(test_client(), Arc::new(ForwardingHandler(tx)))
+            .with_middleware(Arc::new(Rewrite));
+        let err = dispatcher
+            .handle(c2c_message_event("4", "fail"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::HandlerError(HandlerError::Args(ArgError::Missing(1)))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cooldown_and_blacklist() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let blacklist = Blacklist::new(["user_openid"]);
+        let dispatcher = Dispatcher::new(test_client(), Arc::new(ForwardingHandler(tx)))
+            .without_dedupe()
+            .with_middleware(Arc::new(blacklist.clone()))
+            .with_middleware(Arc::new(Cooldown::new(Duration::from_secs(10))));
+
+        dispatcher
+            .handle(c2c_message_event("1", "blocked"))
+            .await
+            .unwrap();
+        dispatcher
+            .handle(group_message_event("2", "first"))
+            .await
+            .unwrap();
+        dispatcher
+            .handle(group_message_event("3", "too soon"))
+            .await
+            .unwrap();
+        tokio::time::advance(Duration::from_secs(11)).await;
+        dispatcher
+            .handle(group_message_event("4", "later"))
+            .await
+            .unwrap();
+
+        // 运行中修改名单
+        assert!(blacklist.remove("user_openid"));
+        assert!(blacklist.insert("group_openid"));
+        dispatcher
+            .handle(group_message_event("5", "group blocked"))
+            .await
+            .unwrap();
+        dispatcher
+            .handle(c2c_message_event("6", "allowed"))
+            .await
+            .unwrap();
+
+        assert_eq!(rx.recv().await.unwrap(), "group:first");
+        assert_eq!(rx.recv().await.unwrap(), "group:later");
+        assert_eq!(rx.recv().await.unwrap(), "c2c:allowed");
+        assert!(rx.try_recv().is_err());
+    }
+
+    /// 群消息内容为等待的秒数
+    struct SleepyHandler;
+
+    #[async_trait]
+    impl QQEvent for SleepyHandler {
+        async fn on_group_at_message_create(
+            &self,
+            message: GroupMessage,
+            _client: &QQClient,
+        ) -> Result<(), ClientError> {
+            let secs = message.content.trim().parse().unwrap();
+            tokio::time::sleep(Duration::from_secs(secs)).await;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout_sends_fallback_reply() {
+        let (client, api) = start_mock_api(Vec::new()).await;
+        let dispatcher =
+            Dispatcher::new(client, Arc::new(SleepyHandler)).with_middleware(Arc::new(
+                Timeout::new(Duration::from_secs(60))
+                    .with_event(MessageKind::GroupAtMessageCreate, Duration::from_millis(50))
+                    .with_fallback_reply("处理超时"),
+            ));
+
+        dispatcher
+            .handle(group_message_event("1", "0"))
+            .await
+            .unwrap();
+        let err = dispatcher
+            .handle(group_message_event("2", "30"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::HandlerError(HandlerError::Timeout(t)) if t == Duration::from_millis(50)
+        ));
+
+        let requests = api.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["content"], "处理超时");
+        assert_eq!(requests[0]["msg_id"], "2");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_passive_reply_window_expires() {
+        let event = Event::decode(group_message_event("1", "hi"))
+            .unwrap()
+            .unwrap();
+        let message = event.message().unwrap();
+        let client = test_client();
+
+        tokio::time::advance(Duration::from_secs(4 * 60)).await;
+        assert_eq!(message.reply_remaining(), Some(Duration::from_secs(60)));
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!(message.reply_remaining(), None);
+        let err = client.reply(&message, "late").await.unwrap_err();
+        assert!(matches!(
+            err,
+            ClientError::PassiveReplyExpired { window, .. } if window == GROUP_REPLY_WINDOW
+        ));
+
+        // 超时时被动回复已过期，不再发送兜底回复
+        let dispatcher = Dispatcher::new(client, Arc::new(SleepyHandler)).with_middleware(
+            Arc::new(Timeout::new(Duration::from_secs(6 * 60)).with_fallback_reply("处理超时")),
+        );
+        let err = dispatcher
+            .handle(group_message_event("2", "3600"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::HandlerError(HandlerError::Timeout(_))
+        ));
+    }
+}
@@ -1,3 +1,11 @@
 pub mod client;
+pub mod conversation;
+pub mod dedupe;
+pub mod dispatcher;
+pub mod handle;
+pub mod middleware;
+pub mod rate_limit;
+pub mod retry;
 pub mod server;
 pub mod websocket;
+pub mod worker;
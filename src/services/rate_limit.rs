@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::{Instant, sleep};
+use tracing::debug;
+
+use crate::models::rate_limit_error::RateLimitError;
+
+/// 超过该数量的令牌桶时，清理空闲的令牌桶
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// 出站请求的路由类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Route {
+    /// 群聊消息，按 group_openid 限流
+    GroupMessage,
+    /// 单聊消息，按 user_openid 限流
+    C2CMessage,
+    /// 频道消息，按 channel_id 限流
+    ChannelMessage,
+}
+
+impl Route {
+    /// 路由对应的 API 路径
+    pub fn path(&self, target: &str) -> String {
+        match self {
+            Route::GroupMessage => format!("/v2/groups/{target}/messages"),
+            Route::C2CMessage => format!("/v2/users/{target}/messages"),
+            Route::ChannelMessage => format!("/v2/channels/{target}/messages"),
+        }
+    }
+}
+
+/// 单个令牌桶的配置
+#[derive(Debug, Clone, Copy)]
+pub struct BucketConfig {
+    burst: u32,
+    per_second: f64,
+}
+
+impl BucketConfig {
+    /// `burst` 为桶容量，即允许的突发请求数；`per_second` 为每秒补充的令牌数
+    pub fn new(burst: u32, per_second: f64) -> Result<Self, RateLimitError> {
+        if burst == 0 {
+            return Err(RateLimitError::ZeroBurst);
+        }
+        if !(per_second.is_finite() && per_second > 0.0) {
+            return Err(RateLimitError::InvalidRefillRate(per_second));
+        }
+        Ok(Self { burst, per_second })
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    pub fn per_second(&self) -> f64 {
+        self.per_second
+    }
+}
+
+impl Default for BucketConfig {
+    fn default() -> Self {
+        Self {
+            burst: 5,
+            per_second: 1.0,
+        }
+    }
+}
+
+/// 限流配置，令牌桶按 (路由, 目标) 划分
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitConfig {
+    /// 未单独配置的路由使用的令牌桶配置
+    pub default: BucketConfig,
+    /// 按路由覆盖的令牌桶配置
+    pub routes: HashMap<Route, BucketConfig>,
+}
+
+impl RateLimitConfig {
+    pub fn new(default: BucketConfig) -> Self {
+        Self {
+            default,
+            routes: HashMap::new(),
+        }
+    }
+
+    pub fn with_route(mut self, route: Route, bucket: BucketConfig) -> Self {
+        self.routes.insert(route, bucket);
+        self
+    }
+
+    fn bucket_config(&self, route: Route) -> BucketConfig {
+        self.routes.get(&route).copied().unwrap_or(self.default)
+    }
+}
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl BucketState {
+    fn refill(&mut self, config: BucketConfig) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.per_second).min(config.burst as f64);
+        self.last_refill = now;
+    }
+}
+
+struct Bucket {
+    config: BucketConfig,
+    /// tokio 的 Mutex 是公平锁，等待者按到达顺序排队
+    state: tokio::sync::Mutex<BucketState>,
+    waiting: AtomicUsize,
+}
+
+impl Bucket {
+    fn new(config: BucketConfig) -> Self {
+        Self {
+            config,
+            state: tokio::sync::Mutex::new(BucketState {
+                tokens: config.burst as f64,
+                last_refill: Instant::now(),
+            }),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        match self.state.try_lock() {
+            Ok(mut state) => {
+                state.refill(self.config);
+                state.tokens >= self.config.burst as f64
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// 排队计数守卫，请求被取消时也能正确减少计数
+struct WaitGuard<'a> {
+    counters: [&'a AtomicUsize; 2],
+}
+
+impl<'a> WaitGuard<'a> {
+    fn new(counters: [&'a AtomicUsize; 2]) -> Self {
+        for counter in counters {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        Self { counters }
+    }
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        for counter in self.counters {
+            counter.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// 令牌桶限流器，令牌不足时排队等待而不是直接失败
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<(Route, String), Arc<Bucket>>>,
+    waiting: AtomicUsize,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// 获取一个令牌，令牌不足时等待
+    pub async fn acquire(&self, route: Route, target: &str) {
+        let bucket = self.bucket(route, target);
+        let _guard = WaitGuard::new([&self.waiting, &bucket.waiting]);
+
+        let mut state = bucket.state.lock().await;
+        state.refill(bucket.config);
+        if state.tokens < 1.0 {
+            let wait = Duration::from_secs_f64((1.0 - state.tokens) / bucket.config.per_second);
+            debug!("{:?} {} 触发限流，等待 {:?}", route, target, wait);
+            sleep(wait).await;
+            state.refill(bucket.config);
+        }
+        state.tokens -= 1.0;
+    }
+
+    /// 所有令牌桶中正在排队的请求总数
+    pub fn queue_depth(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    /// 指定路由和目标上正在排队的请求数
+    pub fn queue_depth_of(&self, route: Route, target: &str) -> usize {
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets
+            .get(&(route, target.to_owned()))
+            .map(|b| b.waiting.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    fn bucket(&self, route: Route, target: &str) -> Arc<Bucket> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (route, target.to_owned());
+        if let Some(bucket) = buckets.get(&key) {
+            return bucket.clone();
+        }
+
+        if buckets.len() >= MAX_IDLE_BUCKETS {
+            buckets.retain(|_, b| Arc::strong_count(b) > 1 || !b.is_idle());
+        }
+
+        let bucket = Arc::new(Bucket::new(self.config.bucket_config(route)));
+        buckets.insert(key, bucket.clone());
+        bucket
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_burst_then_queue() {
+        let limiter = RateLimiter::new(RateLimitConfig::new(BucketConfig::new(2, 1.0).unwrap()));
+        let start = Instant::now();
+
+        limiter.acquire(Route::GroupMessage, "group").await;
+        limiter.acquire(Route::GroupMessage, "group").await;
+        assert_eq!(Instant::now(), start);
+
+        limiter.acquire(Route::GroupMessage, "group").await;
+        assert!(Instant::now() - start >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_invalid_bucket_config_rejected() {
+        assert_eq!(
+            BucketConfig::new(1, 0.0).unwrap_err(),
+            RateLimitError::InvalidRefillRate(0.0)
+        );
+        assert_eq!(
+            BucketConfig::new(1, -1.0).unwrap_err(),
+            RateLimitError::InvalidRefillRate(-1.0)
+        );
+        assert!(matches!(
+            BucketConfig::new(1, f64::NAN),
+            Err(RateLimitError::InvalidRefillRate(_))
+        ));
+        assert_eq!(
+            BucketConfig::new(0, 1.0).unwrap_err(),
+            RateLimitError::ZeroBurst
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_queue_depth_per_target() {
+        let limiter = Arc::new(RateLimiter::new(RateLimitConfig::new(
+            BucketConfig::new(1, 1.0).unwrap(),
+        )));
+        limiter.acquire(Route::C2CMessage, "user_a").await;
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let limiter = limiter.clone();
+                tokio::spawn(async move { limiter.acquire(Route::C2CMessage, "user_a").await })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+
+        assert_eq!(limiter.queue_depth_of(Route::C2CMessage, "user_a"), 3);
+        assert_eq!(limiter.queue_depth(), 3);
+
+        // 其他目标不受影响
+        limiter.acquire(Route::C2CMessage, "user_b").await;
+
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(limiter.queue_depth(), 0);
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::models::{api_error::ApiErrorKind, client_error::ClientError};
+
+type RetryPredicate = Arc<dyn Fn(&ClientError) -> bool + Send + Sync>;
+
+/// API 请求的重试策略，默认只重试网络错误、5xx 和限流错误
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// 最大尝试次数（包含第一次请求）
+    pub max_attempts: u32,
+    /// 第一次重试前的等待时间
+    pub base_delay: Duration,
+    /// 单次等待时间上限
+    pub max_delay: Duration,
+    /// 每次重试等待时间的增长倍数，不小于 1
+    multiplier: f64,
+    /// 随机抖动比例，0.2 表示 ±20%
+    jitter: f64,
+    retry_on: RetryPredicate,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// 增长倍数，小于 1 时按 1 处理
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = clamp_multiplier(multiplier);
+        self
+    }
+
+    /// 抖动比例，限制在 `0.0..=1.0` 内
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = clamp_jitter(jitter);
+        self
+    }
+
+    /// 自定义哪些错误需要重试
+    pub fn with_retry_on<F>(mut self, retry_on: F) -> Self
+    where
+        F: Fn(&ClientError) -> bool + Send + Sync + 'static,
+    {
+        self.retry_on = Arc::new(retry_on);
+        self
+    }
+
+    /// 第 `attempt` 次请求失败后需要等待的时间
+    pub fn delay(&self, attempt: u32) -> Duration {
+        backoff_delay(
+            self.base_delay,
+            self.max_delay,
+            self.multiplier,
+            self.jitter,
+            attempt.saturating_sub(1),
+        )
+    }
+
+    /// 判断错误是否可以重试
+    ///
+    /// 非幂等请求只在确定请求没有被服务端处理时重试（连接失败或被限流拒绝），
+    /// 避免重复发送。
+    pub fn should_retry(&self, err: &ClientError, idempotent: bool) -> bool {
+        if !(self.retry_on)(err) {
+            return false;
+        }
+        if idempotent {
+            return true;
+        }
+        match err {
+            ClientError::NetworkError(e) => e.is_connect(),
+            _ => err.api_kind() == Some(ApiErrorKind::RateLimited),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+            retry_on: Arc::new(is_transient),
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 增长倍数小于 1（或为 NaN）时按 1 处理，保证等待时间不会随失败次数减少
+pub(crate) fn clamp_multiplier(multiplier: f64) -> f64 {
+    multiplier.max(1.0)
+}
+
+/// 抖动比例限制在 `0.0..=1.0` 内，NaN 按 0 处理
+pub(crate) fn clamp_jitter(jitter: f64) -> f64 {
+    if jitter.is_nan() {
+        0.0
+    } else {
+        jitter.clamp(0.0, 1.0)
+    }
+}
+
+/// 指数退避：`base * multiplier^exponent`，不超过 `max`，再加入 ±`jitter` 的随机抖动
+///
+/// `multiplier` 和 `jitter` 需要先经过 [`clamp_multiplier`] 和 [`clamp_jitter`]。
+pub(crate) fn backoff_delay(
+    base: Duration,
+    max: Duration,
+    multiplier: f64,
+    jitter: f64,
+    exponent: u32,
+) -> Duration {
+    let exp = multiplier.powi(exponent.min(i32::MAX as u32) as i32);
+    let delay = (base.as_secs_f64() * exp).min(max.as_secs_f64());
+    let delay = if jitter > 0.0 {
+        let mut rng = rand::rng();
+        delay * rng.random_range((1.0 - jitter)..=(1.0 + jitter))
+    } else {
+        delay
+    };
+    Duration::from_secs_f64(delay)
+}
+
+/// 默认的重试判断：网络错误、服务端错误和限流
+pub fn is_transient(err: &ClientError) -> bool {
+    match err {
+        ClientError::NetworkError(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+        ClientError::Api(e) => matches!(
+            e.kind(),
+            ApiErrorKind::ServerError | ApiErrorKind::RateLimited
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exponential_delay_is_capped() {
+        let policy = RetryPolicy::new(5)
+            .with_base_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(500))
+            .with_jitter(0.0);
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_delay_never_decreases() {
+        for multiplier in [-2.0, 0.0, 0.5, f64::NAN, 1.0, 3.0] {
+            let policy = RetryPolicy::new(10)
+                .with_base_delay(Duration::from_millis(100))
+                .with_max_delay(Duration::from_secs(5))
+                .with_multiplier(multiplier)
+                .with_jitter(0.0);
+            let delays: Vec<_> = (1..=10).map(|attempt| policy.delay(attempt)).collect();
+            assert_eq!(delays[0], Duration::from_millis(100));
+            assert!(
+                delays.windows(2).all(|w| w[0] <= w[1]),
+                "multiplier {multiplier}: {delays:?}"
+            );
+        }
+    }
+}
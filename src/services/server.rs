@@ -1,5 +1,5 @@
-use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
 use axum::{
     Router,
@@ -8,33 +8,57 @@ use axum::{
     routing::post,
 };
 use serde::Serialize;
-use strum::EnumString;
-use tokio::net::ToSocketAddrs;
-use tracing::{debug, error, info};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    config::Config,
+    config::{Config, RunMode},
     event_client::{DefaultEventHandler, QQEvent},
     models::{
         error::AppError,
         event::{OpCode, QQBotEvent},
-        message::{C2CMessage, GroupMessage},
         server_error::ServerError,
     },
-    services::{client::QQClient, websocket},
+    services::{
+        client::QQClient,
+        conversation::Conversations,
+        dedupe::DedupeStore,
+        dispatcher::{DispatchMetrics, Dispatcher},
+        handle::BotHandle,
+        middleware::Middleware,
+        rate_limit::RateLimitConfig,
+        retry::RetryPolicy,
+        websocket::{ShardManager, reconnect::ReconnectPolicy, store::SessionStore},
+        worker::WorkerPoolConfig,
+    },
     utils::validation::validate_webhook,
 };
 
+pub use crate::services::dispatcher::{EventType, MessageKind};
+
 #[derive(Clone)]
 struct AppState {
-    client: QQClient,
     config: Config,
-    event_handler: Arc<dyn QQEvent>,
+    dispatcher: Dispatcher,
 }
 
 pub struct ServerBuilder {
     config: Config,
     event_handler: Option<Arc<dyn QQEvent>>,
+    rate_limit: Option<RateLimitConfig>,
+    retry_policy: Option<RetryPolicy>,
+    session_store: Option<Arc<dyn SessionStore>>,
+    shards: Option<(u32, Vec<u32>)>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    shutdown: CancellationToken,
+    dedupe_store: Option<Arc<dyn DedupeStore>>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    worker_pool: Option<WorkerPoolConfig>,
+    /// 启动后的分片管理器，供 [`BotHandle`] 查询连接状态
+    shard_manager: Arc<OnceLock<ShardManager>>,
+    metrics: Arc<DispatchMetrics>,
+    conversations: Conversations,
 }
 
 impl ServerBuilder {
@@ -42,46 +66,222 @@ impl ServerBuilder {
         Self {
             config,
             event_handler: None,
+            rate_limit: None,
+            retry_policy: None,
+            session_store: None,
+            shards: None,
+            reconnect_policy: None,
+            shutdown: CancellationToken::new(),
+            dedupe_store: None,
+            middlewares: Vec::new(),
+            worker_pool: None,
+            shard_manager: Default::default(),
+            metrics: Default::default(),
+            conversations: Conversations::new(),
         }
     }
 
+    /// 获取控制句柄，可在其他任务中查询连接状态或调用 [`BotHandle::shutdown`] 优雅关闭
+    pub fn handle(&self) -> BotHandle {
+        BotHandle::new(
+            self.shutdown.clone(),
+            self.shard_manager.clone(),
+            self.metrics.clone(),
+            self.conversations.clone(),
+        )
+    }
+
     pub fn with_event_handler(mut self, handler: impl QQEvent + 'static) -> Self {
         self.event_handler = Some(Arc::new(handler));
         self
     }
 
+    /// 启用出站 API 请求的限流，默认不限流
+    pub fn with_rate_limit(mut self, config: RateLimitConfig) -> Self {
+        self.rate_limit = Some(config);
+        self
+    }
+
+    /// 设置出站 API 请求的重试策略
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// 设置网关会话的持久化存储，进程重启后可以 Resume 之前的会话
+    pub fn with_session_store(mut self, store: impl SessionStore + 'static) -> Self {
+        self.session_store = Some(Arc::new(store));
+        self
+    }
+
+    /// 设置事件去重存储，未设置时使用 [`MemoryDedupeStore`](crate::services::dedupe::MemoryDedupeStore)
+    pub fn with_dedupe_store(mut self, store: impl DedupeStore + 'static) -> Self {
+        self.dedupe_store = Some(Arc::new(store));
+        self
+    }
+
+    /// 添加事件处理中间件，先添加的在外层
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// 设置事件处理的并发上限和会话内顺序，未设置时使用 [`WorkerPoolConfig::default`]
+    pub fn with_worker_pool(mut self, config: WorkerPoolConfig) -> Self {
+        self.worker_pool = Some(config);
+        self
+    }
+
+    /// 设置网关重连策略，未设置时根据配置中的 `timing` 生成
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = Some(policy);
+        self
+    }
+
+    /// 指定总分片数和本进程负责的分片，未设置时按网关建议的分片数启动全部分片
+    pub fn with_shards(
+        mut self,
+        total_shards: u32,
+        shard_ids: impl IntoIterator<Item = u32>,
+    ) -> Self {
+        self.shards = Some((total_shards, shard_ids.into_iter().collect()));
+        self
+    }
+
+    /// 设置接收事件的方式，覆盖配置中的 `mode`
+    pub fn with_mode(mut self, mode: RunMode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// 按配置中的 `mode` 启动，需要 WebHook 时监听 `listen_addr`
+    pub async fn run(self) -> Result<(), ServerError> {
+        let listener = if self.config.mode.uses_webhook() {
+            Some(TcpListener::bind(&self.config.listen_addr).await?)
+        } else {
+            None
+        };
+        self.serve(listener).await
+    }
+
+    /// 在指定地址启动，仅网关模式下忽略该地址
     pub async fn start<A: ToSocketAddrs>(self, addr: A) -> Result<(), ServerError> {
-        info!("启动中...");
-        let client = QQClient::new(self.config.clone());
+        let listener = if self.config.mode.uses_webhook() {
+            Some(TcpListener::bind(addr).await?)
+        } else {
+            None
+        };
+        self.serve(listener).await
+    }
+
+    async fn serve(self, listener: Option<TcpListener>) -> Result<(), ServerError> {
+        let mode = self.config.mode;
+        info!("启动中，模式: {:?}", mode);
+        let mut client = QQClient::new(self.config.clone());
+        if let Some(rate_limit) = self.rate_limit {
+            client = client.with_rate_limit(rate_limit);
+        }
+        if let Some(retry_policy) = self.retry_policy {
+            client = client.with_retry_policy(retry_policy);
+        }
         info!("鉴权中...");
         client.auth().await?;
-        let wss_url = client.get_wss_endpoint().await?;
-
-        info!("会话启动中...");
-        let ws_client = client.clone();
-        tokio::spawn(async move {
-            websocket::start(wss_url, ws_client).await;
-        });
 
         let event_handler = self
             .event_handler
             .unwrap_or_else(|| Arc::new(DefaultEventHandler));
+        let mut dispatcher = Dispatcher::new(client.clone(), event_handler)
+            .with_metrics(self.metrics.clone())
+            .with_conversations(self.conversations.clone());
+        if let Some(store) = self.dedupe_store {
+            dispatcher = dispatcher.with_dedupe_store(store);
+        }
+        if let Some(config) = self.worker_pool {
+            dispatcher = dispatcher.with_worker_pool(config);
+        }
+        for middleware in self.middlewares {
+            dispatcher = dispatcher.with_middleware(middleware);
+        }
 
-        let state = AppState {
-            client,
-            config: self.config,
-            event_handler,
+        let shard_manager = if mode.uses_gateway() {
+            info!("会话启动中...");
+            let mut shard_manager = ShardManager::new(client)
+                .with_dispatcher(dispatcher.clone())
+                .with_shutdown(self.shutdown.clone());
+            if let Some((total_shards, shard_ids)) = self.shards {
+                shard_manager = shard_manager
+                    .with_total_shards(total_shards)
+                    .with_shard_ids(shard_ids);
+            }
+            if let Some(store) = self.session_store {
+                shard_manager = shard_manager.with_session_store(store);
+            }
+            if let Some(policy) = self.reconnect_policy {
+                shard_manager = shard_manager.with_reconnect_policy(policy);
+            }
+            shard_manager.start().await?;
+            let _ = self.shard_manager.set(shard_manager.clone());
+            Some(shard_manager)
+        } else {
+            None
         };
 
-        let app = Router::new()
-            .route("/", post(qq_bot_event_handler))
-            .with_state(state);
+        let shutdown_timeout = Duration::from_secs(self.config.timing.shutdown_timeout_secs);
+        let served: Result<(), ServerError> = match (listener, &shard_manager) {
+            (Some(listener), _) => {
+                let webhook_path = self.config.webhook_path.clone();
+                let state = AppState {
+                    config: self.config,
+                    dispatcher: dispatcher.clone(),
+                };
+                let app = Router::new()
+                    .route(&webhook_path, post(qq_bot_event_handler))
+                    .with_state(state);
 
-        let listener = tokio::net::TcpListener::bind(addr).await?;
+                if let Ok(addr) = listener.local_addr() {
+                    info!("WebHook 监听于 {}{}", addr, webhook_path);
+                }
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(self.shutdown.clone().cancelled_owned())
+                    .await
+                    .map_err(ServerError::from)
+            }
+            (None, Some(shard_manager)) => {
+                // 仅网关模式：运行到请求关闭，或所有分片都放弃重连
+                tokio::select! {
+                    _ = self.shutdown.cancelled() => {}
+                    _ = shard_manager.join() => warn!("所有分片均已停止"),
+                }
+                Ok(())
+            }
+            (None, None) => unreachable!("RunMode 至少启用网关或 WebHook 之一"),
+        };
+        if let Err(err) = &served {
+            // WebHook 服务异常退出时同样停止分片，走完下面的关闭流程再返回错误
+            error!("WebHook 服务异常退出: {}", err);
+            self.shutdown.cancel();
+        }
 
-        axum::serve(listener, app).await?;
+        info!("正在关闭，等待处理中的事件...");
+        let tasks = dispatcher.tasks();
+        tasks.close();
+        let drained = tokio::time::timeout(shutdown_timeout, async {
+            if let Some(shard_manager) = &shard_manager {
+                shard_manager.join().await;
+            }
+            tasks.wait().await;
+        })
+        .await;
+        if drained.is_err() {
+            warn!(
+                "等待 {:?} 后仍有 {} 个事件未处理完成，强制关闭",
+                shutdown_timeout,
+                tasks.len()
+            );
+        }
+        info!("已关闭");
 
-        Ok(())
+        served
     }
 }
 
@@ -105,12 +305,8 @@ async fn qq_bot_event_handler(
     match OpCode::try_from(payload.op) {
         Ok(op) => match op {
             OpCode::Dispatch => {
-                // 使用 tokio::spawn 异步处理事件，不阻塞 WebHook 响应
-                tokio::spawn(async move {
-                    if let Err(e) = dispatch_event(payload, state).await {
-                        error!("Error handling dispatch event: {:?}", e);
-                    }
-                });
+                // 异步处理事件，不阻塞 WebHook 响应
+                state.dispatcher.dispatch(payload);
                 Ok(Json(callback_ack).into_response())
             }
             OpCode::WebhookValidate => {
@@ -135,54 +331,3 @@ async fn qq_bot_event_handler(
         }
     }
 }
-
-async fn dispatch_event(payload: QQBotEvent, state: AppState) -> Result<(), AppError> {
-    if let Some(t) = &payload.t {
-        debug!("Event Type: {}", t);
-
-        match EventType::from_str(t) {
-            Ok(ty) => match ty {
-                EventType::GroupAtMessageCreate => {
-                    let message: GroupMessage =
-                        serde_json::from_value(payload.d.unwrap_or_default())
-                            .map_err(AppError::SerializationError)?;
-
-                    state
-                        .event_handler
-                        .on_group_at_message_create(message, &state.client)
-                        .await
-                        .map_err(AppError::ClientError)?;
-                }
-                EventType::C2CMessageCreate => {
-                    let message: C2CMessage = serde_json::from_value(payload.d.unwrap_or_default())
-                        .map_err(AppError::SerializationError)?;
-
-                    state
-                        .event_handler
-                        .on_c2c_message_create(message, &state.client)
-                        .await
-                        .map_err(AppError::ClientError)?;
-                }
-                _ => {}
-            },
-            Err(err) => {
-                error!("Failed to parse event type: {}", err);
-                return Err(AppError::ValidationError(format!(
-                    "Unknown event type: {}",
-                    t
-                )));
-            }
-        }
-    }
-    Ok(())
-}
-
-#[derive(Debug, EnumString)]
-pub enum EventType {
-    #[strum(serialize = "GROUP_AT_MESSAGE_CREATE")]
-    GroupAtMessageCreate,
-    #[strum(serialize = "READY")]
-    Ready,
-    #[strum(serialize = "C2C_MESSAGE_CREATE")]
-    C2CMessageCreate,
-}
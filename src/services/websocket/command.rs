@@ -0,0 +1,55 @@
+use tokio::sync::mpsc;
+
+use crate::services::websocket::error::WebSocketError;
+
+/// 断线期间最多缓存的指令数，缓存满时发送方等待
+pub(crate) const COMMAND_BUFFER: usize = 64;
+
+/// 通过网关连接发送的指令
+#[derive(Debug, Clone)]
+pub enum GatewayCommand {
+    /// 立即发送一次心跳，并重新开始心跳计时
+    Heartbeat,
+    /// 发送原始 payload
+    Raw {
+        op: u8,
+        d: Option<serde_json::Value>,
+    },
+}
+
+/// 网关指令发送端，可以自由克隆
+///
+/// 指令与心跳由同一个写端串行发送；连接断开期间指令会被缓存，
+/// 重连并就绪（READY / RESUMED）后按顺序发出。
+#[derive(Debug, Clone)]
+pub struct GatewaySender {
+    tx: mpsc::Sender<GatewayCommand>,
+}
+
+impl GatewaySender {
+    pub(crate) fn new(tx: mpsc::Sender<GatewayCommand>) -> Self {
+        Self { tx }
+    }
+
+    /// 发送指令，缓存已满时等待
+    pub async fn send(&self, command: GatewayCommand) -> Result<(), WebSocketError> {
+        self.tx
+            .send(command)
+            .await
+            .map_err(|_| WebSocketError::CommandChannelClosed)
+    }
+
+    /// 立即发送一次心跳
+    pub async fn heartbeat(&self) -> Result<(), WebSocketError> {
+        self.send(GatewayCommand::Heartbeat).await
+    }
+
+    /// 发送原始 payload
+    pub async fn send_raw(
+        &self,
+        op: u8,
+        d: Option<serde_json::Value>,
+    ) -> Result<(), WebSocketError> {
+        self.send(GatewayCommand::Raw { op, d }).await
+    }
+}
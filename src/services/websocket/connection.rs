@@ -3,31 +3,31 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use futures_util::{SinkExt, StreamExt};
-use rand::Rng;
-use tokio::time::{Instant, interval_at, sleep};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{Instant, interval_at, sleep, timeout};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, frame::coding::CloseCode};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
+use crate::models::api_error::ApiErrorKind;
 use crate::models::event::{OpCode, QQBotEvent};
 use crate::services::client::QQClient;
-use crate::services::server::EventType;
+use crate::services::dispatcher::{Dispatcher, EventType};
+use crate::services::websocket::command::{COMMAND_BUFFER, GatewayCommand, GatewaySender};
 use crate::services::websocket::error::WebSocketError;
-use crate::services::websocket::state::SessionState;
+use crate::services::websocket::latency::RttWindow;
+use crate::services::websocket::reconnect::{GiveUp, ReconnectPolicy};
+use crate::services::websocket::shard::{IdentifyLimiter, SessionStartBudget, ShardStatus};
+use crate::services::websocket::state::{ConnectionState, SessionState};
+use crate::services::websocket::store::SessionStore;
 
-// 常量定义
-const MAX_RESUME_RETRIES: u32 = 3;
-#[cfg(not(test))]
-const RESUME_WAIT_SECONDS: u64 = 30;
-#[cfg(test)]
-const RESUME_WAIT_SECONDS: u64 = 1;
+/// 主动关闭时等待服务端回应 Close 帧的最长时间
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
 
-#[cfg(not(test))]
-const HEARTBEAT_TIMEOUT_SECONDS: u64 = 7;
-#[cfg(test)]
-const HEARTBEAT_TIMEOUT_SECONDS: u64 = 2; // 测试时稍微长一点以免误判，但比 7s 短
-
-const RECONNECT_BASE_DELAY_MS: u64 = 1000;
-const RECONNECT_MAX_DELAY_MS: u64 = 5000;
+/// 需要保留会话时使用的关闭码，以 1000 关闭会使服务端废弃当前会话
+const RESUMABLE_CLOSE_CODE: u16 = 4000;
 
 /// WebSocket 管理器，负责维护连接、心跳和状态恢复
 pub struct WebSocketManager {
@@ -39,95 +39,354 @@ pub struct WebSocketManager {
     state: Arc<SessionState>,
     /// 当前连续 Resume 失败次数
     resume_count: u32,
+    /// 连续无法建立连接（未收到 Hello）的次数
+    connect_failures: u32,
+    /// 本次连接是否收到了 Hello
+    hello_received: bool,
+    /// 重连策略
+    reconnect_policy: ReconnectPolicy,
+    /// 分片信息 [shard_id, total_shards]
+    shard: [u32; 2],
+    /// 会话持久化存储
+    session_store: Option<Arc<dyn SessionStore>>,
+    /// 多个分片共享的 Identify 限速器
+    identify_limiter: Option<Arc<IdentifyLimiter>>,
+    /// Session 创建额度缓存
+    session_budget: Arc<SessionStartBudget>,
+    /// 当前连接的心跳 RTT 样本
+    rtt: RttWindow,
+    /// 平均 RTT 是否超过了 `timing.heartbeat_degraded_ms`
+    degraded: bool,
+    /// 事件分发器，未设置时只处理连接生命周期事件
+    dispatcher: Option<Dispatcher>,
+    /// 外部指令的发送端，用于创建 [`GatewaySender`]
+    commands_tx: mpsc::Sender<GatewayCommand>,
+    /// 外部指令，跨连接保留，断线期间的指令在重连后发送
+    commands: mpsc::Receiver<GatewayCommand>,
+    /// 对外发布的连接状态
+    connection: watch::Sender<ConnectionState>,
+    /// 关闭信号
+    shutdown: CancellationToken,
 }
 
 impl WebSocketManager {
     /// 创建新的 WebSocket 管理器
     pub async fn new(wss_url: String, client: QQClient) -> Self {
         let state = Arc::new(SessionState::new());
+        let reconnect_policy = ReconnectPolicy::from(&client.config().timing);
+        let (commands_tx, commands) = mpsc::channel(COMMAND_BUFFER);
         Self {
             wss_url,
             client,
             state,
             resume_count: 0,
+            connect_failures: 0,
+            hello_received: false,
+            reconnect_policy,
+            shard: [0, 1],
+            session_store: None,
+            identify_limiter: None,
+            session_budget: Default::default(),
+            dispatcher: None,
+            commands_tx,
+            commands,
+            rtt: RttWindow::default(),
+            degraded: false,
+            connection: watch::Sender::new(ConnectionState::new(0)),
+            shutdown: CancellationToken::new(),
         }
     }
 
+    /// 使用持久化存储保存会话，重启后可以直接 Resume
+    pub fn with_session_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.session_store = Some(store);
+        self.reset_state();
+        self
+    }
+
+    /// 设置分片，`shard_id` 从 0 开始
+    pub fn with_shard(mut self, shard_id: u32, total_shards: u32) -> Self {
+        self.shard = [shard_id, total_shards];
+        self.connection
+            .send_modify(|state| state.shard_id = shard_id);
+        self.reset_state();
+        self
+    }
+
+    /// 设置重连策略，默认根据配置中的 `timing` 生成
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// 设置 Identify 限速器，多个分片共享同一个限速器
+    pub fn with_identify_limiter(mut self, limiter: Arc<IdentifyLimiter>) -> Self {
+        self.identify_limiter = Some(limiter);
+        self
+    }
+
+    /// 设置 Session 创建额度缓存，多个分片共享同一份额度
+    pub fn with_session_budget(mut self, budget: Arc<SessionStartBudget>) -> Self {
+        self.session_budget = budget;
+        self
+    }
+
+    /// 设置事件分发器，收到的 Dispatch 事件交给它处理
+    pub fn with_dispatcher(mut self, dispatcher: Dispatcher) -> Self {
+        self.dispatcher = Some(dispatcher);
+        self
+    }
+
+    /// 设置关闭信号，取消后发送 Close 帧断开连接，[`WebSocketManager::start`] 返回
+    pub fn with_shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// 订阅连接状态变化
+    pub fn subscribe(&self) -> watch::Receiver<ConnectionState> {
+        self.connection.subscribe()
+    }
+
+    /// 获取网关指令发送端，指令与心跳经由同一写端发送
+    pub fn command_sender(&self) -> GatewaySender {
+        GatewaySender::new(self.commands_tx.clone())
+    }
+
+    fn set_status(&self, status: ShardStatus) {
+        self.connection.send_modify(|state| state.status = status);
+    }
+
+    /// 将会话状态同步到对外发布的连接状态
+    async fn publish_session(&self) {
+        let session_id = self.state.get_session_id().await;
+        let last_seq = self.state.get_last_seq().await;
+        self.connection.send_if_modified(|state| {
+            if state.session_id == session_id && state.last_seq == last_seq {
+                return false;
+            }
+            state.session_id = session_id;
+            state.last_seq = last_seq;
+            true
+        });
+    }
+
+    fn reset_state(&mut self) {
+        self.state = Arc::new(match &self.session_store {
+            Some(store) => SessionState::with_store(store.clone(), self.shard[0]),
+            None => SessionState::new(),
+        });
+    }
+
+    /// 运行连接循环，断开后按重连策略自动重连
+    ///
+    /// 收到关闭信号，或重连策略为 [`GiveUp::Stop`] 且连续失败达到上限时返回。
     pub async fn start(&mut self) {
+        match self.state.restore().await {
+            Ok(true) => info!("已从存储中恢复会话，将尝试 Resume"),
+            Ok(false) => {}
+            Err(e) => warn!("恢复会话失败，将重新 Identify: {:?}", e),
+        }
+        self.publish_session().await;
+
+        let mut reconnecting = false;
         loop {
-            match self.connect_and_loop().await {
+            if reconnecting {
+                self.connection
+                    .send_modify(|state| state.reconnect_count += 1);
+            }
+            reconnecting = true;
+
+            let result = self.connect_and_loop().await;
+            if let Err(e) = self.state.persist().await {
+                warn!("保存会话失败: {:?}", e);
+            }
+            if self.shutdown.is_cancelled() {
+                info!("分片 {:?} 已关闭", self.shard);
+                self.set_status(ShardStatus::Closed);
+                return;
+            }
+            // 本次连接曾经就绪，说明之前的失败已经恢复，重新计数
+            if self.connection.borrow().status == ShardStatus::Ready {
+                self.resume_count = 0;
+            }
+
+            if self.hello_received {
+                self.connect_failures = 0;
+            } else if result.is_err() {
+                self.connect_failures += 1;
+                let refresh_after = self.client.config().timing.refresh_url_after_failures;
+                if refresh_after > 0 && self.connect_failures >= refresh_after {
+                    warn!(
+                        "连续 {} 次无法连接网关，重新获取网关地址",
+                        self.connect_failures
+                    );
+                    self.refresh_gateway_url().await;
+                    self.connect_failures = 0;
+                }
+            }
+
+            match result {
                 Ok(_) => {
                     debug!("WebSocket 连接正常关闭");
                     self.resume_count = 0;
+                    self.wait_base_delay().await;
+                }
+                Err(WebSocketError::ReconnectRequested) => {
+                    info!("分片 {:?} 按服务端要求重连，将 Resume 当前会话", self.shard);
+                    // 服务端要求重连时网关地址可能已经变化
+                    self.refresh_gateway_url().await;
+                    self.wait_base_delay().await;
+                }
+                Err(WebSocketError::SessionStartLimitExhausted { total, reset_after }) => {
+                    error!(
+                        "分片 {:?} 的 Session 创建次数已用尽（每日 {} 次），{:?} 后重试",
+                        self.shard, total, reset_after
+                    );
+                    self.set_status(ShardStatus::SessionLimited);
+                    self.sleep_or_shutdown(reset_after).await;
                 }
                 Err(e) => {
                     match e {
                         WebSocketError::HeartbeatTimeout
                         | WebSocketError::ConnectionClosed
                         | WebSocketError::Io(_) => {
-                            error!("WebSocket 异常断开: {:?}", e);
                             // 这些错误通常意味着网络问题，尝试 Resume
-                            self.handle_reconnect_delay().await;
+                            error!("WebSocket 异常断开: {:?}", e);
                         }
-                        WebSocketError::InvalidSession => {
+                        WebSocketError::InvalidSession { resumable: true } => {
+                            warn!("会话暂时无效，稍后尝试 Resume");
+                        }
+                        WebSocketError::InvalidSession { resumable: false } => {
                             warn!("会话无效或过期，尝试重新鉴权...");
                             if let Err(e) = self.client.auth().await {
                                 error!("重新鉴权失败: {:?}", e);
                             } else {
                                 info!("重新鉴权成功，Token 已更新");
                             }
-                            // 鉴权后等待一段时间再重连
-                            self.handle_reconnect_delay().await;
                         }
                         _ => {
                             // 其他错误可能需要重置会话
                             // 例如 InvalidSession 已经在 connect_and_loop 内部处理并清空了状态
-                            self.handle_reconnect_delay().await;
+                            error!("WebSocket 连接失败: {:?}", e);
                         }
                     }
+
+                    if !self.handle_reconnect_delay().await {
+                        self.set_status(ShardStatus::Closed);
+                        return;
+                    }
                 }
             }
         }
     }
 
-    async fn handle_reconnect_delay(&mut self) {
-        if self.resume_count >= MAX_RESUME_RETRIES {
-            warn!(
-                "连续重连失败 {} 次，暂停 {} 秒",
-                self.resume_count, RESUME_WAIT_SECONDS
-            );
-            sleep(Duration::from_secs(RESUME_WAIT_SECONDS)).await;
-            self.resume_count = 0;
-        } else {
-            let delay_ms = {
-                let mut rng = rand::rng();
-                // 基础延迟 + 随机抖动 (+-20%)
-                rng.random_range(
-                    (RECONNECT_BASE_DELAY_MS as f64 * 0.8) as u64
-                        ..=(RECONNECT_BASE_DELAY_MS as f64 * 1.2) as u64,
-                )
-            };
+    /// 重新获取网关地址，Token 失效时先重新鉴权；失败时继续使用原地址
+    async fn refresh_gateway_url(&mut self) {
+        let result = match self.client.get_wss_endpoint().await {
+            Err(e) if e.api_kind() == Some(ApiErrorKind::Unauthorized) => {
+                warn!("获取网关地址时鉴权失败，重新鉴权...");
+                match self.client.auth().await {
+                    Ok(()) => self.client.get_wss_endpoint().await,
+                    Err(e) => Err(e),
+                }
+            }
+            result => result,
+        };
 
-            // 简单的指数退避也可以考虑，但这里使用固定范围+抖动
-            let final_delay = std::cmp::min(
-                delay_ms * (self.resume_count as u64 + 1),
-                RECONNECT_MAX_DELAY_MS,
-            );
+        match result {
+            Ok(url) => {
+                if url != self.wss_url {
+                    info!("网关地址已更新: {}", url);
+                    self.wss_url = url;
+                }
+            }
+            Err(e) => warn!("获取网关地址失败，继续使用 {}: {:?}", self.wss_url, e),
+        }
+    }
 
-            info!("将在 {}ms 后尝试重连...", final_delay);
-            sleep(Duration::from_millis(final_delay)).await;
-            self.resume_count += 1;
+    /// 按重连策略等待，返回 `false` 表示放弃重连
+    async fn handle_reconnect_delay(&mut self) -> bool {
+        self.set_status(ShardStatus::BackingOff);
+
+        if self.reconnect_policy.exhausted(self.resume_count) {
+            match self.reconnect_policy.give_up {
+                GiveUp::Stop => {
+                    error!(
+                        "分片 {:?} 连续重连失败 {} 次，停止重连",
+                        self.shard, self.resume_count
+                    );
+                    return false;
+                }
+                GiveUp::Pause(pause) => {
+                    warn!("连续重连失败 {} 次，暂停 {:?}", self.resume_count, pause);
+                    self.sleep_or_shutdown(pause).await;
+                    self.resume_count = 0;
+                    return true;
+                }
+            }
+        }
+
+        let delay = self.reconnect_policy.delay(self.resume_count);
+        info!("将在 {:?} 后尝试重连...", delay);
+        self.sleep_or_shutdown(delay).await;
+        self.resume_count += 1;
+        !self.shutdown.is_cancelled()
+    }
+
+    /// 不计入失败次数的重连（正常关闭、服务端要求重连）前按基础延迟等待，避免频繁重连
+    async fn wait_base_delay(&self) {
+        self.set_status(ShardStatus::BackingOff);
+        let delay = self.reconnect_policy.delay(0);
+        debug!("将在 {:?} 后重新连接", delay);
+        self.sleep_or_shutdown(delay).await;
+    }
+
+    /// 等待指定时间，收到关闭信号时提前返回
+    async fn sleep_or_shutdown(&self, duration: Duration) {
+        tokio::select! {
+            _ = sleep(duration) => {}
+            _ = self.shutdown.cancelled() => {}
         }
     }
 
     async fn connect_and_loop(&mut self) -> Result<(), WebSocketError> {
+        // 需要 Identify 时先等待限速，避免连接建立后长时间不发送 Identify
+        let session_id = self.state.get_session_id().await;
+        let last_seq = self.state.get_last_seq().await;
+        let resumable = session_id.is_some() && last_seq.is_some();
+        let shutdown = self.shutdown.clone();
+        self.hello_received = false;
+        if shutdown.is_cancelled() {
+            return Ok(());
+        }
+        if !resumable {
+            self.check_session_start_limit().await?;
+            if let Some(limiter) = &self.identify_limiter {
+                tokio::select! {
+                    _ = limiter.wait(self.shard[0]) => {}
+                    _ = shutdown.cancelled() => return Ok(()),
+                }
+            }
+        }
+
+        self.set_status(ShardStatus::Connecting);
         debug!("正在连接 WebSocket: {}", self.wss_url);
-        let (ws_stream, _) = connect_async(&self.wss_url).await?;
+        let (ws_stream, _) = tokio::select! {
+            result = connect_async(&self.wss_url) => result?,
+            _ = shutdown.cancelled() => return Ok(()),
+        };
         let (mut write, mut read) = ws_stream.split();
 
         // 1. 等待 Hello 包以获取心跳间隔
         let heartbeat_interval_ms = loop {
-            match read.next().await {
+            let msg = tokio::select! {
+                msg = read.next() => msg,
+                _ = shutdown.cancelled() => {
+                    return Self::close(&mut write, &mut read, CloseCode::Normal).await;
+                }
+            };
+            match msg {
                 Some(Ok(Message::Text(text))) => {
                     let event = serde_json::from_str::<QQBotEvent>(&text)?;
                     if OpCode::try_from(event.op).unwrap_or(OpCode::Dispatch) == OpCode::Hello
@@ -136,6 +395,7 @@ impl WebSocketManager {
                         if let Some(interval) = d.get("heartbeat_interval").and_then(|v| v.as_u64())
                         {
                             debug!("收到 Hello，心跳间隔: {}ms", interval);
+                            self.hello_received = true;
                             break interval;
                         }
                         return Err(WebSocketError::MissingHeartbeatInterval);
@@ -150,15 +410,22 @@ impl WebSocketManager {
             }
         };
 
-        // 2. Identify 或 Resume
-        let session_id = self.state.get_session_id().await;
-        let last_seq = self.state.get_last_seq().await;
+        // 新连接重新统计心跳 RTT
+        self.rtt.clear();
+        self.degraded = false;
+        self.connection.send_modify(|state| {
+            state.heartbeat_rtt = None;
+            state.heartbeat_rtt_avg = None;
+        });
 
+        // 2. Identify 或 Resume
         if let (Some(sid), Some(seq)) = (session_id, last_seq) {
             debug!("尝试 Resume Session: {}, Seq: {}", sid, seq);
+            self.set_status(ShardStatus::Resuming);
             self.send_resume(&mut write, &sid, seq).await?;
         } else {
             debug!("发送 Identify");
+            self.set_status(ShardStatus::Identifying);
             self.send_identify(&mut write).await?;
         }
 
@@ -170,12 +437,21 @@ impl WebSocketManager {
         );
         heartbeat_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
+        let heartbeat_timeout_secs = self.client.config().timing.heartbeat_timeout_secs;
         let mut awaiting_ack = false;
+        // 最近一次心跳的发送时间，用于计算 RTT
+        let mut heartbeat_sent: Option<Instant> = None;
         // 超时检查器，初始设置为永不触发
         let mut ack_timeout = Box::pin(sleep(Duration::MAX));
 
         loop {
             tokio::select! {
+                // 关闭信号
+                _ = shutdown.cancelled() => {
+                    info!("正在关闭分片 {:?} 的连接", self.shard);
+                    return Self::close(&mut write, &mut read, CloseCode::Normal).await;
+                }
+
                 // 接收消息
                 msg = read.next() => {
                     match msg {
@@ -193,6 +469,7 @@ impl WebSocketManager {
                             // 更新 last_seq (如果有)
                             if let Some(s) = event.s {
                                 self.state.update(None, Some(s)).await?;
+                                self.publish_session().await;
                             }
 
                             match OpCode::try_from(event.op).unwrap_or(OpCode::Dispatch) {
@@ -203,18 +480,34 @@ impl WebSocketManager {
                                 OpCode::HeartbeatACK => {
                                     debug!("收到 HeartbeatACK");
                                     awaiting_ack = false;
+                                    if let Some(sent) = heartbeat_sent.take() {
+                                        self.record_rtt(sent.elapsed());
+                                    }
                                     // 取消超时计时
                                     ack_timeout = Box::pin(sleep(Duration::MAX));
                                 }
                                 OpCode::InvalidSession => {
-                                    warn!("收到 InvalidSession，会话失效，清理状态");
-                                    self.state.update(None, None).await?; // 清空状态
-                                    // 这里返回错误，触发重连，重连时会因为没有状态而走 Identify
-                                    return Err(WebSocketError::InvalidSession);
+                                    // d 为 true 时会话仍可 Resume，否则需要重新 Identify
+                                    let resumable =
+                                        matches!(event.d, Some(serde_json::Value::Bool(true)));
+                                    let code = if resumable {
+                                        warn!("收到 InvalidSession，会话可恢复，重连后 Resume");
+                                        CloseCode::from(RESUMABLE_CLOSE_CODE)
+                                    } else {
+                                        warn!("收到 InvalidSession，会话失效，清理状态");
+                                        self.state.clear().await?; // 清空状态
+                                        self.publish_session().await;
+                                        CloseCode::Normal
+                                    };
+                                    // 服务端可能已经断开，关闭失败不影响重连
+                                    let _ = Self::close(&mut write, &mut read, code).await;
+                                    return Err(WebSocketError::InvalidSession { resumable });
                                 }
                                 OpCode::Reconnect => {
-                                    debug!("服务端要求重连");
-                                    return Ok(());
+                                    info!("服务端要求重连");
+                                    let code = CloseCode::from(RESUMABLE_CLOSE_CODE);
+                                    let _ = Self::close(&mut write, &mut read, code).await;
+                                    return Err(WebSocketError::ReconnectRequested);
                                 }
                                 OpCode::Heartbeat => {
                                     // 服务端请求心跳，立即回复一次
@@ -239,15 +532,40 @@ impl WebSocketManager {
                 _ = heartbeat_interval.tick() => {
                     debug!("发送心跳...");
                     self.send_heartbeat(&mut write).await?;
+                    heartbeat_sent = Some(Instant::now());
+                    if let Err(e) = self.state.persist().await {
+                        warn!("保存会话失败: {:?}", e);
+                    }
                     awaiting_ack = true;
                     // 启动超时计时
-                    ack_timeout = Box::pin(sleep(Duration::from_secs(HEARTBEAT_TIMEOUT_SECONDS)));
+                    ack_timeout = Box::pin(sleep(Duration::from_secs(heartbeat_timeout_secs)));
+                }
+
+                // 外部指令，就绪后才发送，断线期间留在队列中
+                Some(command) = self.commands.recv(),
+                    if self.connection.borrow().status == ShardStatus::Ready =>
+                {
+                    match command {
+                        GatewayCommand::Heartbeat => {
+                            debug!("收到立即心跳指令");
+                            heartbeat_interval.reset_immediately();
+                        }
+                        GatewayCommand::Raw { op, d } => {
+                            debug!("发送外部指令 op: {}", op);
+                            let event = QQBotEvent {
+                                op,
+                                d,
+                                ..Default::default()
+                            };
+                            Self::send_payload(&mut write, &event).await?;
+                        }
+                    }
                 }
 
                 // 心跳超时检测
                 _ = &mut ack_timeout => {
                     if awaiting_ack {
-                        error!("心跳超时！未在 {} 秒内收到 ACK", HEARTBEAT_TIMEOUT_SECONDS);
+                        error!("心跳超时！未在 {} 秒内收到 ACK", heartbeat_timeout_secs);
                         return Err(WebSocketError::HeartbeatTimeout);
                     }
                 }
@@ -255,6 +573,100 @@ impl WebSocketManager {
         }
     }
 
+    /// 记录一次心跳 RTT，平均值越过 `timing.heartbeat_degraded_ms` 时输出日志
+    fn record_rtt(&mut self, rtt: Duration) {
+        let avg = self.rtt.record(rtt);
+        debug!("心跳 RTT: {:?}，平均: {:?}", rtt, avg);
+        self.connection.send_modify(|state| {
+            state.heartbeat_rtt = Some(rtt);
+            state.heartbeat_rtt_avg = Some(avg);
+        });
+
+        let threshold_ms = self.client.config().timing.heartbeat_degraded_ms;
+        if threshold_ms == 0 {
+            return;
+        }
+        let degraded = avg > Duration::from_millis(threshold_ms);
+        if degraded && !self.degraded {
+            warn!(
+                "分片 {:?} 连接质量下降，平均心跳 RTT {:?} 超过 {}ms",
+                self.shard, avg, threshold_ms
+            );
+        } else if !degraded && self.degraded {
+            info!(
+                "分片 {:?} 连接质量已恢复，平均心跳 RTT {:?}",
+                self.shard, avg
+            );
+        }
+        self.degraded = degraded;
+    }
+
+    /// Identify 前检查 Session 创建额度，额度用尽时返回错误并等待重置
+    ///
+    /// 优先使用缓存的额度，缓存失效时才请求 `/gateway/bot`；获取额度失败时不阻塞 Identify，
+    /// 由网关自行拒绝。
+    async fn check_session_start_limit(&self) -> Result<(), WebSocketError> {
+        if self.session_budget.is_stale() {
+            match self.client.get_gateway_bot().await {
+                Ok(gateway) => self.session_budget.update(&gateway.session_start_limit),
+                Err(e) => {
+                    warn!("获取 Session 创建额度失败，继续 Identify: {:?}", e);
+                    return Ok(());
+                }
+            }
+        }
+        self.session_budget.acquire()
+    }
+
+    /// 发送 Close 帧，并在 [`CLOSE_TIMEOUT`] 内等待服务端回应
+    async fn close<S, R, E>(
+        write: &mut S,
+        read: &mut R,
+        code: CloseCode,
+    ) -> Result<(), WebSocketError>
+    where
+        S: SinkExt<Message> + Unpin,
+        S::Error: std::error::Error + Send + Sync + 'static,
+        R: StreamExt<Item = Result<Message, E>> + Unpin,
+    {
+        let reason = if code == CloseCode::Normal {
+            "shutdown"
+        } else {
+            "reconnect"
+        };
+        let frame = CloseFrame {
+            code,
+            reason: reason.into(),
+        };
+        write
+            .send(Message::Close(Some(frame)))
+            .await
+            .map_err(|e| WebSocketError::SendFailed(e.to_string()))?;
+
+        let _ = timeout(CLOSE_TIMEOUT, async {
+            while let Some(Ok(msg)) = read.next().await {
+                if msg.is_close() {
+                    break;
+                }
+            }
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn send_payload<S>(write: &mut S, event: &QQBotEvent) -> Result<(), WebSocketError>
+    where
+        S: SinkExt<Message> + Unpin,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let json = serde_json::to_string(event)?;
+        write
+            .send(Message::Text(json.into()))
+            .await
+            .map_err(|e| WebSocketError::SendFailed(e.to_string()))?;
+        Ok(())
+    }
+
     async fn send_heartbeat<S>(&self, write: &mut S) -> Result<(), WebSocketError>
     where
         S: SinkExt<Message> + Unpin,
@@ -272,12 +684,7 @@ impl WebSocketManager {
             ..Default::default()
         };
 
-        let json = serde_json::to_string(&event)?;
-        write
-            .send(Message::Text(json.into()))
-            .await
-            .map_err(|e| WebSocketError::SendFailed(e.to_string()))?;
-        Ok(())
+        Self::send_payload(write, &event).await
     }
 
     async fn send_identify<S>(&self, write: &mut S) -> Result<(), WebSocketError>
@@ -295,8 +702,14 @@ impl WebSocketManager {
             "token".to_owned(),
             serde_json::Value::String(format!("QQBot {}", token)),
         );
-        map.insert("intents".to_owned(), serde_json::to_value(1 << 30).unwrap());
-        map.insert("shard".to_owned(), serde_json::to_value([0, 1]).unwrap());
+        map.insert(
+            "intents".to_owned(),
+            serde_json::to_value(self.client.config().intents).unwrap(),
+        );
+        map.insert(
+            "shard".to_owned(),
+            serde_json::to_value(self.shard).unwrap(),
+        );
 
         let event = QQBotEvent {
             op: OpCode::Identify.into(),
@@ -304,12 +717,7 @@ impl WebSocketManager {
             ..Default::default()
         };
 
-        let json = serde_json::to_string(&event)?;
-        write
-            .send(Message::Text(json.into()))
-            .await
-            .map_err(|e| WebSocketError::SendFailed(e.to_string()))?;
-        Ok(())
+        Self::send_payload(write, &event).await
     }
 
     async fn send_resume<S>(
@@ -344,49 +752,59 @@ impl WebSocketManager {
             ..Default::default()
         };
 
-        let json = serde_json::to_string(&event)?;
-        write
-            .send(Message::Text(json.into()))
-            .await
-            .map_err(|e| WebSocketError::SendFailed(e.to_string()))?;
-        Ok(())
+        Self::send_payload(write, &event).await
     }
 
     async fn handle_dispatch(&self, event: QQBotEvent) -> Result<(), WebSocketError> {
         // 提取 Ready 事件中的 session_id
         // 注意：OpCode 0 (Dispatch) 包含各种事件，Ready 是其中一种，由 event.t 区分
-        let Some(t) = event.t else {
+        let Some(t) = event.t.as_deref() else {
             return Ok(());
         };
 
-        if let Ok(t) = EventType::from_str(&t) {
+        if let Ok(t) = EventType::from_str(t) {
             match t {
                 EventType::Ready => {
                     if let Some(serde_json::Value::Object(d)) = &event.d {
                         if let Some(serde_json::Value::String(session_id)) = d.get("session_id") {
                             debug!("Ready 事件，获取到 session_id: {}", session_id);
                             self.state.update(Some(session_id.clone()), None).await?;
+                            self.publish_session().await;
                         }
                         if let Some(v) = d.get("user")
                             && let Some(username) = v.get("username").and_then(|u| u.as_str())
                         {
-                            info!("机器人: [{}] 启动成功! 就绪！", username);
+                            info!(
+                                "机器人: [{}] 分片 {:?} 启动成功! 就绪！",
+                                username, self.shard
+                            );
                         }
                     }
+                    self.set_status(ShardStatus::Ready);
                 }
-                _ => {
-                    // TODO: 分发其他事件到 EventBus 或 Handler
-                    // 这里只是打印日志
-                    debug!("Dispatch Event: {:?}", t);
+                EventType::Resumed => {
+                    self.set_status(ShardStatus::Ready);
+                    info!("分片 {:?} 会话已恢复", self.shard);
                 }
+                _ => debug!("Dispatch Event: {:?}", t),
             }
         }
 
+        if let Some(dispatcher) = &self.dispatcher {
+            dispatcher.dispatch(event);
+        }
         Ok(())
     }
 }
 
-pub async fn start(wss_url: String, client: QQClient) {
+pub async fn start(
+    wss_url: String,
+    client: QQClient,
+    session_store: Option<Arc<dyn SessionStore>>,
+) {
     let mut manager = WebSocketManager::new(wss_url, client).await;
+    if let Some(store) = session_store {
+        manager = manager.with_session_store(store);
+    }
     manager.start().await;
 }
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -23,8 +25,17 @@ pub enum WebSocketError {
     #[error("Missing heartbeat interval in Hello payload")]
     MissingHeartbeatInterval,
 
-    #[error("Invalid Session")]
-    InvalidSession,
+    #[error("Invalid Session (resumable: {resumable})")]
+    InvalidSession { resumable: bool },
+
+    #[error("Gateway requested reconnect")]
+    ReconnectRequested,
+
+    #[error("Gateway command channel closed")]
+    CommandChannelClosed,
+
+    #[error("Session start limit exhausted ({total} per day), resets after {reset_after:?}")]
+    SessionStartLimitExhausted { total: u32, reset_after: Duration },
 
     #[error("Other error: {0}")]
     Other(String),
@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// 参与平均值计算的心跳样本数
+const DEFAULT_WINDOW: usize = 10;
+
+/// 心跳 RTT 的滑动窗口，只保留最近的若干个样本
+#[derive(Debug, Clone)]
+pub struct RttWindow {
+    samples: VecDeque<Duration>,
+    capacity: usize,
+}
+
+impl RttWindow {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// 记录一次 RTT，窗口已满时丢弃最旧的样本，返回新的平均值
+    pub fn record(&mut self, rtt: Duration) -> Duration {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rtt);
+        self.average().unwrap_or(rtt)
+    }
+
+    /// 当前窗口内的平均 RTT，没有样本时返回 `None`
+    pub fn average(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+impl Default for RttWindow {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
@@ -1,7 +1,13 @@
+pub mod command;
 pub mod connection;
 pub mod error;
+pub mod latency;
+pub mod reconnect;
+pub mod shard;
 pub mod state;
+pub mod store;
 #[cfg(test)]
 mod tests;
 
 pub use connection::start;
+pub use shard::ShardManager;
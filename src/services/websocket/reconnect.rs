@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use crate::config::TimingConfig;
+use crate::services::retry::{backoff_delay, clamp_jitter, clamp_multiplier};
+
+/// 连续重连失败达到上限后的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GiveUp {
+    /// 暂停一段时间后重新开始计数
+    Pause(Duration),
+    /// 停止重连，[`WebSocketManager::start`](super::connection::WebSocketManager::start) 返回
+    Stop,
+}
+
+/// 重连策略，等待时间按指数增长并加入随机抖动
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// 第一次重连前的等待时间
+    pub base_delay: Duration,
+    /// 单次等待时间上限
+    pub max_delay: Duration,
+    /// 每次失败后等待时间的增长倍数，不小于 1
+    multiplier: f64,
+    /// 随机抖动比例，0.2 表示 ±20%
+    jitter: f64,
+    /// 连续失败多少次后触发 `give_up`，`None` 表示不限次数
+    pub max_attempts: Option<u32>,
+    /// 达到 `max_attempts` 后的处理方式
+    pub give_up: GiveUp,
+}
+
+impl ReconnectPolicy {
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// 增长倍数，小于 1 时按 1 处理
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = clamp_multiplier(multiplier);
+        self
+    }
+
+    /// 抖动比例，限制在 `0.0..=1.0` 内
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = clamp_jitter(jitter);
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: Option<u32>) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn with_give_up(mut self, give_up: GiveUp) -> Self {
+        self.give_up = give_up;
+        self
+    }
+
+    /// 是否已达到连续失败上限
+    pub fn exhausted(&self, failures: u32) -> bool {
+        self.max_attempts.is_some_and(|max| failures >= max)
+    }
+
+    /// 第 `failures + 1` 次重连前的等待时间
+    pub fn delay(&self, failures: u32) -> Duration {
+        backoff_delay(
+            self.base_delay,
+            self.max_delay,
+            self.multiplier,
+            self.jitter,
+            failures,
+        )
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self::from(&TimingConfig::default())
+    }
+}
+
+impl From<&TimingConfig> for ReconnectPolicy {
+    fn from(timing: &TimingConfig) -> Self {
+        let give_up = if timing.stop_after_max_retries {
+            GiveUp::Stop
+        } else {
+            GiveUp::Pause(Duration::from_secs(timing.resume_wait_secs))
+        };
+
+        Self {
+            base_delay: Duration::from_millis(timing.reconnect_base_delay_ms),
+            max_delay: Duration::from_millis(timing.reconnect_max_delay_ms),
+            multiplier: clamp_multiplier(timing.reconnect_multiplier),
+            jitter: clamp_jitter(timing.reconnect_jitter),
+            max_attempts: (timing.max_resume_retries > 0).then_some(timing.max_resume_retries),
+            give_up,
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::FutureExt;
+use tokio::sync::watch;
+use tokio::time::{Instant, sleep_until};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, info, warn};
+
+use crate::models::client_error::ClientError;
+use crate::models::gateway::SessionStartLimit;
+use crate::services::client::QQClient;
+use crate::services::dispatcher::Dispatcher;
+use crate::services::websocket::command::GatewaySender;
+use crate::services::websocket::connection::WebSocketManager;
+use crate::services::websocket::error::WebSocketError;
+use crate::services::websocket::reconnect::ReconnectPolicy;
+use crate::services::websocket::state::ConnectionState;
+use crate::services::websocket::store::SessionStore;
+
+/// 每个并发桶两次 Identify 之间的最小间隔
+const IDENTIFY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 分片的连接状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardStatus {
+    /// 尚未开始连接
+    Pending,
+    /// 正在连接
+    Connecting,
+    /// 已发送 Identify，等待 READY
+    Identifying,
+    /// 已发送 Resume，等待 RESUMED
+    Resuming,
+    /// 已就绪，正在接收事件
+    Ready,
+    /// 连接断开，按重连策略等待
+    BackingOff,
+    /// Session 创建次数已用尽，等待额度重置
+    SessionLimited,
+    /// 已关闭或放弃重连，不会再连接
+    Closed,
+}
+
+/// Identify 限速器，保证每个并发桶每 5 秒最多发送一次 Identify
+///
+/// 分片按 `shard_id % max_concurrency` 分配到并发桶。
+pub struct IdentifyLimiter {
+    buckets: Vec<tokio::sync::Mutex<Option<Instant>>>,
+}
+
+impl IdentifyLimiter {
+    pub fn new(max_concurrency: u32) -> Self {
+        let buckets = (0..max_concurrency.max(1))
+            .map(|_| tokio::sync::Mutex::new(None))
+            .collect();
+        Self { buckets }
+    }
+
+    /// 等待直到该分片可以发送 Identify
+    pub async fn wait(&self, shard_id: u32) {
+        let bucket = &self.buckets[shard_id as usize % self.buckets.len()];
+        let mut last = bucket.lock().await;
+        if let Some(last) = *last {
+            let next = last + IDENTIFY_INTERVAL;
+            if next > Instant::now() {
+                debug!("分片 {} 等待 Identify 限速", shard_id);
+                sleep_until(next).await;
+            }
+        }
+        *last = Some(Instant::now());
+    }
+}
+
+/// Session 创建额度的本地缓存，多个分片共享，避免每次 Identify 都请求 `/gateway/bot`
+///
+/// 每次 Identify 前扣减本地计数；超过 `reset_after` 后缓存失效，下次 Identify 前重新获取。
+#[derive(Default)]
+pub struct SessionStartBudget {
+    cached: Mutex<Option<CachedLimit>>,
+}
+
+struct CachedLimit {
+    total: u32,
+    remaining: u32,
+    reset_at: Instant,
+}
+
+impl SessionStartBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 使用已经获取到的额度初始化
+    pub fn with_limit(limit: &SessionStartLimit) -> Self {
+        let budget = Self::new();
+        budget.update(limit);
+        budget
+    }
+
+    /// 更新为开放平台返回的最新额度
+    pub fn update(&self, limit: &SessionStartLimit) {
+        *self.lock() = Some(CachedLimit {
+            total: limit.total,
+            remaining: limit.remaining,
+            reset_at: Instant::now() + Duration::from_millis(limit.reset_after),
+        });
+    }
+
+    /// 缓存不存在或已过重置时间，需要重新获取
+    pub fn is_stale(&self) -> bool {
+        self.lock()
+            .as_ref()
+            .is_none_or(|cached| Instant::now() >= cached.reset_at)
+    }
+
+    /// Identify 前扣减一次额度，额度用尽时返回错误；没有可用缓存时不做限制
+    pub(crate) fn acquire(&self) -> Result<(), WebSocketError> {
+        let now = Instant::now();
+        let mut cached = self.lock();
+        let Some(cached) = cached.as_mut().filter(|c| now < c.reset_at) else {
+            return Ok(());
+        };
+        debug!(
+            "Session 创建额度: {}/{}，{:?} 后重置",
+            cached.remaining,
+            cached.total,
+            cached.reset_at - now
+        );
+        if cached.remaining == 0 {
+            return Err(WebSocketError::SessionStartLimitExhausted {
+                total: cached.total,
+                reset_after: cached.reset_at - now,
+            });
+        }
+        cached.remaining -= 1;
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<CachedLimit>> {
+        self.cached.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 已启动分片的状态订阅和指令发送端
+#[derive(Clone)]
+struct ShardEntry {
+    state: watch::Receiver<ConnectionState>,
+    commands: GatewaySender,
+}
+
+/// 分片管理器，为每个分片启动一个 [`WebSocketManager`]
+///
+/// 默认按 `/gateway/bot` 建议的分片数启动全部分片；多进程部署时可以通过
+/// [`ShardManager::with_shard_ids`] 让每个进程只负责其中一部分分片。
+#[derive(Clone)]
+pub struct ShardManager {
+    client: QQClient,
+    /// 总分片数，未设置时使用 `/gateway/bot` 建议的分片数
+    total_shards: Option<u32>,
+    /// 本进程负责的分片，未设置时启动全部分片
+    shard_ids: Option<Vec<u32>>,
+    session_store: Option<Arc<dyn SessionStore>>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    dispatcher: Option<Dispatcher>,
+    shutdown: CancellationToken,
+    shards: Arc<Mutex<BTreeMap<u32, ShardEntry>>>,
+    tasks: TaskTracker,
+}
+
+impl ShardManager {
+    pub fn new(client: QQClient) -> Self {
+        Self {
+            client,
+            total_shards: None,
+            shard_ids: None,
+            session_store: None,
+            reconnect_policy: None,
+            dispatcher: None,
+            shutdown: CancellationToken::new(),
+            shards: Default::default(),
+            tasks: Default::default(),
+        }
+    }
+
+    pub fn with_total_shards(mut self, total_shards: u32) -> Self {
+        self.total_shards = Some(total_shards);
+        self
+    }
+
+    pub fn with_shard_ids(mut self, shard_ids: impl IntoIterator<Item = u32>) -> Self {
+        self.shard_ids = Some(shard_ids.into_iter().collect());
+        self
+    }
+
+    pub fn with_session_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.session_store = Some(store);
+        self
+    }
+
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = Some(policy);
+        self
+    }
+
+    /// 设置事件分发器，所有分片收到的事件都交给它处理
+    pub fn with_dispatcher(mut self, dispatcher: Dispatcher) -> Self {
+        self.dispatcher = Some(dispatcher);
+        self
+    }
+
+    /// 设置关闭信号，取消后所有分片断开连接
+    pub fn with_shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// 获取网关信息并在后台启动所有分片
+    pub async fn start(&self) -> Result<(), ClientError> {
+        let gateway = self.client.get_gateway_bot().await?;
+        let total = self.total_shards.unwrap_or(gateway.shards).max(1);
+        let shard_ids: Vec<u32> = match &self.shard_ids {
+            Some(ids) => ids.clone(),
+            None => (0..total).collect(),
+        };
+
+        info!(
+            "启动分片 {:?}，总分片数 {}，max_concurrency {}",
+            shard_ids, total, gateway.session_start_limit.max_concurrency
+        );
+        let limiter = Arc::new(IdentifyLimiter::new(
+            gateway.session_start_limit.max_concurrency,
+        ));
+        let budget = Arc::new(SessionStartBudget::with_limit(&gateway.session_start_limit));
+
+        for shard_id in shard_ids {
+            if shard_id >= total {
+                warn!("分片 {} 超出总分片数 {}，已忽略", shard_id, total);
+                continue;
+            }
+
+            let mut manager = WebSocketManager::new(gateway.url.clone(), self.client.clone())
+                .await
+                .with_shard(shard_id, total)
+                .with_identify_limiter(limiter.clone())
+                .with_session_budget(budget.clone())
+                .with_shutdown(self.shutdown.clone());
+            if let Some(store) = &self.session_store {
+                manager = manager.with_session_store(store.clone());
+            }
+            if let Some(policy) = &self.reconnect_policy {
+                manager = manager.with_reconnect_policy(policy.clone());
+            }
+            if let Some(dispatcher) = &self.dispatcher {
+                manager = manager.with_dispatcher(dispatcher.clone());
+            }
+
+            self.shards
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(
+                    shard_id,
+                    ShardEntry {
+                        state: manager.subscribe(),
+                        commands: manager.command_sender(),
+                    },
+                );
+            self.tasks.spawn(async move {
+                if AssertUnwindSafe(manager.start())
+                    .catch_unwind()
+                    .await
+                    .is_err()
+                {
+                    warn!("分片 {} 任务异常退出", shard_id);
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// 等待所有分片的连接任务结束，通常在发出关闭信号后调用
+    ///
+    /// 不会取走任务，被取消后再次调用仍会等待同一批分片。
+    pub async fn join(&self) {
+        self.tasks.close();
+        self.tasks.wait().await;
+    }
+
+    /// 各分片当前的状态
+    pub fn status(&self) -> Vec<(u32, ShardStatus)> {
+        self.shards
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(id, entry)| (*id, entry.state.borrow().status))
+            .collect()
+    }
+
+    /// 各分片当前的连接状态详情
+    pub fn connection_states(&self) -> Vec<ConnectionState> {
+        self.shards
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .map(|entry| entry.state.borrow().clone())
+            .collect()
+    }
+
+    /// 订阅某个分片的连接状态变化
+    pub fn subscribe(&self, shard_id: u32) -> Option<watch::Receiver<ConnectionState>> {
+        self.shards
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&shard_id)
+            .map(|entry| entry.state.clone())
+    }
+
+    /// 获取某个分片的网关指令发送端
+    pub fn command_sender(&self, shard_id: u32) -> Option<GatewaySender> {
+        self.shards
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&shard_id)
+            .map(|entry| entry.commands.clone())
+    }
+}
@@ -1,7 +1,13 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
 use crate::services::websocket::error::WebSocketError;
+use crate::services::websocket::shard::ShardStatus;
+use crate::services::websocket::store::SessionStore;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SessionData {
@@ -9,10 +15,46 @@ pub struct SessionData {
     pub last_seq: Option<u64>,
 }
 
+/// 分片连接状态快照，通过 [`WebSocketManager::subscribe`](super::connection::WebSocketManager::subscribe) 订阅
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionState {
+    pub shard_id: u32,
+    pub status: ShardStatus,
+    pub session_id: Option<String>,
+    pub last_seq: Option<u64>,
+    /// 最近一次心跳从发送到收到 ACK 的耗时
+    pub heartbeat_rtt: Option<Duration>,
+    /// 当前连接最近若干次心跳 RTT 的平均值
+    pub heartbeat_rtt_avg: Option<Duration>,
+    /// 启动以来的重连次数
+    pub reconnect_count: u32,
+}
+
+impl ConnectionState {
+    pub(crate) fn new(shard_id: u32) -> Self {
+        Self {
+            shard_id,
+            status: ShardStatus::Pending,
+            session_id: None,
+            last_seq: None,
+            heartbeat_rtt: None,
+            heartbeat_rtt_avg: None,
+            reconnect_count: 0,
+        }
+    }
+}
+
 /// 会话状态管理器，负责内存中存储 session_id 和 last_seq
+///
+/// 配置了 [`SessionStore`] 时，session_id 变化会立即持久化，
+/// last_seq 则由调用方通过 [`SessionState::persist`] 定期保存，没有变化时不会重复写入。
 #[derive(Default)]
 pub struct SessionState {
     data: RwLock<SessionData>,
+    store: Option<Arc<dyn SessionStore>>,
+    shard_id: u32,
+    /// 上次写入存储后会话是否有变化
+    dirty: AtomicBool,
 }
 
 impl SessionState {
@@ -21,19 +63,72 @@ impl SessionState {
         Default::default()
     }
 
+    /// 创建带持久化存储的会话状态管理器
+    pub fn with_store(store: Arc<dyn SessionStore>, shard_id: u32) -> Self {
+        Self {
+            data: Default::default(),
+            store: Some(store),
+            shard_id,
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// 从存储中恢复会话，返回是否恢复到了可用于 Resume 的会话
+    pub async fn restore(&self) -> Result<bool, WebSocketError> {
+        let Some(store) = &self.store else {
+            return Ok(false);
+        };
+        let Some(saved) = store.load(self.shard_id).await? else {
+            return Ok(false);
+        };
+
+        let resumable = saved.session_id.is_some() && saved.last_seq.is_some();
+        *self.data.write().await = saved;
+        Ok(resumable)
+    }
+
+    /// 会话有变化时写入存储
+    pub async fn persist(&self) -> Result<(), WebSocketError> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let data = self.data.read().await.clone();
+        let result = store.save(self.shard_id, &data).await;
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+
     pub async fn update(
         &self,
         session_id: Option<String>,
         last_seq: Option<u64>,
     ) -> Result<(), WebSocketError> {
-        let mut data = self.data.write().await;
+        let session_changed = {
+            let mut data = self.data.write().await;
 
-        if session_id.is_some() && data.session_id != session_id {
-            data.session_id = session_id;
-        }
+            let session_changed = session_id.is_some() && data.session_id != session_id;
+            if session_changed {
+                data.session_id = session_id;
+            }
 
-        if last_seq.is_some() && data.last_seq != last_seq {
-            data.last_seq = last_seq;
+            if last_seq.is_some() && data.last_seq != last_seq {
+                data.last_seq = last_seq;
+                self.dirty.store(true, Ordering::Release);
+            }
+            if session_changed {
+                self.dirty.store(true, Ordering::Release);
+            }
+
+            session_changed
+        };
+
+        if session_changed {
+            self.persist().await?;
         }
 
         Ok(())
@@ -47,9 +142,17 @@ impl SessionState {
         self.data.read().await.last_seq
     }
 
-    pub async fn clear(&self) {
-        let mut data = self.data.write().await;
-        data.session_id = None;
-        data.last_seq = None;
+    pub async fn clear(&self) -> Result<(), WebSocketError> {
+        {
+            let mut data = self.data.write().await;
+            data.session_id = None;
+            data.last_seq = None;
+        }
+        self.dirty.store(false, Ordering::Release);
+
+        if let Some(store) = &self.store {
+            store.clear(self.shard_id).await?;
+        }
+        Ok(())
     }
 }
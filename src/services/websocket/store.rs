@@ -0,0 +1,82 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use crate::services::websocket::error::WebSocketError;
+use crate::services::websocket::state::SessionData;
+
+/// 会话持久化存储，进程重启后可以用保存的 session_id 和 last_seq 进行 Resume
+///
+/// 每个分片的会话独立保存，以 `shard_id` 区分。
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// 读取保存的会话，不存在时返回 `None`
+    async fn load(&self, shard_id: u32) -> Result<Option<SessionData>, WebSocketError>;
+
+    /// 保存会话
+    async fn save(&self, shard_id: u32, data: &SessionData) -> Result<(), WebSocketError>;
+
+    /// 清除保存的会话
+    async fn clear(&self, shard_id: u32) -> Result<(), WebSocketError>;
+}
+
+/// 以 JSON 文件保存会话
+///
+/// 分片 0 使用给定的路径，其他分片在文件名后追加分片号，例如 `session.shard1.json`。
+pub struct FileSessionStore {
+    path: PathBuf,
+}
+
+impl FileSessionStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn path_of(&self, shard_id: u32) -> PathBuf {
+        if shard_id == 0 {
+            return self.path.clone();
+        }
+        let stem = self
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let file_name = match self.path.extension() {
+            Some(ext) => format!("{stem}.shard{shard_id}.{}", ext.to_string_lossy()),
+            None => format!("{stem}.shard{shard_id}"),
+        };
+        self.path.with_file_name(file_name)
+    }
+}
+
+#[async_trait]
+impl SessionStore for FileSessionStore {
+    async fn load(&self, shard_id: u32) -> Result<Option<SessionData>, WebSocketError> {
+        match tokio::fs::read(self.path_of(shard_id)).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&self, shard_id: u32, data: &SessionData) -> Result<(), WebSocketError> {
+        let path = self.path_of(shard_id);
+        let json = serde_json::to_vec(data)?;
+        // 先写临时文件再重命名，避免进程中途退出留下损坏的文件。
+        // 临时文件名在完整文件名后追加，不同分片不会共用
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    async fn clear(&self, shard_id: u32) -> Result<(), WebSocketError> {
+        match tokio::fs::remove_file(self.path_of(shard_id)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,15 +1,44 @@
 use super::connection::WebSocketManager;
-use crate::config::Config;
+use super::error::WebSocketError;
+use super::latency::RttWindow;
+use super::reconnect::{GiveUp, ReconnectPolicy};
+use super::shard::{IdentifyLimiter, ShardStatus};
+use super::state::{SessionData, SessionState};
+use super::store::{FileSessionStore, SessionStore};
+use crate::config::{Config, TimingConfig};
 use crate::models::event::{OpCode, QQBotEvent};
 use crate::services::client::QQClient;
+use crate::test_support::{
+    OFFLINE_API_BASE_URL, client_with_config, start_gateway_api, test_config,
+};
 use futures_util::{SinkExt, StreamExt};
 use serde_json::json;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::TcpListener;
-use tokio_tungstenite::accept_async;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
 use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::{WebSocketStream, accept_async};
 
-async fn start_mock_server(heartbeat_interval: u64) -> (String, tokio::task::JoinHandle<()>) {
+/// 测试使用更短的超时时间
+fn test_timing() -> TimingConfig {
+    TimingConfig {
+        heartbeat_timeout_secs: 2,
+        resume_wait_secs: 1,
+        ..Default::default()
+    }
+}
+
+/// 模拟网关，收到的每个包都会转发到返回的 Receiver
+async fn start_mock_server(
+    heartbeat_interval: u64,
+) -> (
+    String,
+    tokio::task::JoinHandle<()>,
+    mpsc::UnboundedReceiver<QQBotEvent>,
+) {
+    let (received_tx, received_rx) = mpsc::unbounded_channel();
     let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
     let addr = listener.local_addr().unwrap();
     let url = format!("ws://{}/", addr);
@@ -17,6 +46,7 @@ async fn start_mock_server(heartbeat_interval: u64) -> (String, tokio::task::Joi
     let handle = tokio::spawn(async move {
         while let Ok((stream, _)) = listener.accept().await {
             let heartbeat_interval = heartbeat_interval;
+            let received_tx = received_tx.clone();
             tokio::spawn(async move {
                 let mut ws_stream = match accept_async(stream).await {
                     Ok(s) => s,
@@ -42,6 +72,7 @@ async fn start_mock_server(heartbeat_interval: u64) -> (String, tokio::task::Joi
                     if let Ok(Message::Text(text)) = msg {
                         let event: QQBotEvent = serde_json::from_str(&text).unwrap();
                         let op = OpCode::try_from(event.op).unwrap_or(OpCode::Dispatch);
+                        let _ = received_tx.send(event);
                         match op {
                             OpCode::Identify => {
                                 // Send Ready (Dispatch)
@@ -90,17 +121,45 @@ async fn start_mock_server(heartbeat_interval: u64) -> (String, tokio::task::Joi
         }
     });
 
-    (url, handle)
+    (url, handle, received_rx)
+}
+
+fn test_client() -> QQClient {
+    test_client_with_api(OFFLINE_API_BASE_URL)
+}
+
+fn test_client_with_api(api_base_url: &str) -> QQClient {
+    client_with_config(Config {
+        timing: test_timing(),
+        ..test_config(api_base_url)
+    })
+}
+
+/// 等待模拟网关收到第一个 Identify 或 Resume
+async fn next_session_op(received: &mut mpsc::UnboundedReceiver<QQBotEvent>) -> QQBotEvent {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let event = received.recv().await.unwrap();
+            if event.op == u8::from(OpCode::Identify) || event.op == u8::from(OpCode::Resume) {
+                return event;
+            }
+        }
+    })
+    .await
+    .expect("未收到 Identify 或 Resume")
 }
 
 #[tokio::test]
 async fn test_websocket_connect_and_identify() {
-    let (url, _server_handle) = start_mock_server(1000).await;
+    let (url, _server_handle, _received) = start_mock_server(1000).await;
     let token = "test_token".to_string();
 
     let config = Config {
         app_id: "test_app_id".to_string(),
         client_secret: "test_secret".to_string(),
+        timing: test_timing(),
+        api_base_url: Some(OFFLINE_API_BASE_URL.to_string()),
+        ..Default::default()
     };
     let client = QQClient::new(config);
     client.set_access_token(token.clone());
@@ -145,7 +204,7 @@ async fn test_heartbeat_timeout() {
                     .await;
 
                 // Read messages but do NOT send ACK
-                while let Some(_) = ws_stream.next().await {}
+                while ws_stream.next().await.is_some() {}
             });
         }
     });
@@ -153,13 +212,16 @@ async fn test_heartbeat_timeout() {
     let config = Config {
         app_id: "test_app_id".to_string(),
         client_secret: "test_secret".to_string(),
+        timing: test_timing(),
+        api_base_url: Some(OFFLINE_API_BASE_URL.to_string()),
+        ..Default::default()
     };
     let client = QQClient::new(config);
     client.set_access_token("token".into());
 
     let mut manager = WebSocketManager::new(url, client).await;
 
-    // We expect it to connect, send heartbeat, then timeout (after HEARTBEAT_TIMEOUT_SECONDS which is 2s in test), then reconnect
+    // We expect it to connect, send heartbeat, then timeout (after heartbeat_timeout_secs which is 2s in test), then reconnect
     // We can't easily verify the internal error, but we can verify it doesn't crash
 
     tokio::select! {
@@ -167,3 +229,789 @@ async fn test_heartbeat_timeout() {
         _ = tokio::time::sleep(Duration::from_secs(4)) => {}
     }
 }
+
+#[tokio::test]
+async fn test_resume_from_persisted_session() {
+    let (url, _server_handle, mut received) = start_mock_server(1000).await;
+
+    let path = std::env::temp_dir().join(format!("qq-bot-session-{}.json", std::process::id()));
+    let store = Arc::new(FileSessionStore::new(&path));
+    store
+        .save(
+            0,
+            &SessionData {
+                session_id: Some("saved_session".to_string()),
+                last_seq: Some(42),
+            },
+        )
+        .await
+        .unwrap();
+
+    let mut manager = WebSocketManager::new(url, test_client())
+        .await
+        .with_session_store(store.clone());
+    let handle = tokio::spawn(async move {
+        manager.start().await;
+    });
+
+    let event = next_session_op(&mut received).await;
+    handle.abort();
+    store.clear(0).await.unwrap();
+
+    assert_eq!(event.op, u8::from(OpCode::Resume));
+    let d = event.d.unwrap();
+    assert_eq!(d["session_id"], "saved_session");
+    assert_eq!(d["seq"], 42);
+}
+
+#[tokio::test]
+async fn test_file_store_shards_use_separate_temp_files() {
+    // 无扩展名时分片 0 为 `session`，分片 1 为 `session.shard1`
+    let path = std::env::temp_dir().join(format!("qq-bot-session-{}", std::process::id()));
+    let store = FileSessionStore::new(&path);
+    let session = |id: &str, seq| SessionData {
+        session_id: Some(id.to_string()),
+        last_seq: Some(seq),
+    };
+
+    for seq in 0..20 {
+        let (shard0, shard1) = (session("shard0", seq), session("shard1", seq));
+        let (a, b) = tokio::join!(store.save(0, &shard0), store.save(1, &shard1));
+        a.unwrap();
+        b.unwrap();
+    }
+    let shard0 = store.load(0).await.unwrap().unwrap();
+    let shard1 = store.load(1).await.unwrap().unwrap();
+    store.clear(0).await.unwrap();
+    store.clear(1).await.unwrap();
+
+    assert_eq!(shard0.session_id.as_deref(), Some("shard0"));
+    assert_eq!(shard1.session_id.as_deref(), Some("shard1"));
+}
+
+/// 记录保存次数的会话存储
+#[derive(Default)]
+struct CountingStore {
+    saves: std::sync::atomic::AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl SessionStore for CountingStore {
+    async fn load(&self, _shard_id: u32) -> Result<Option<SessionData>, WebSocketError> {
+        Ok(None)
+    }
+
+    async fn save(&self, _shard_id: u32, _data: &SessionData) -> Result<(), WebSocketError> {
+        self.saves
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn clear(&self, _shard_id: u32) -> Result<(), WebSocketError> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_session_persisted_only_when_changed() {
+    let store = Arc::new(CountingStore::default());
+    let saves = || store.saves.load(std::sync::atomic::Ordering::Relaxed);
+    let state = SessionState::with_store(store.clone(), 0);
+
+    // session_id 变化立即保存
+    state.update(Some("session".into()), Some(1)).await.unwrap();
+    assert_eq!(saves(), 1);
+    state.persist().await.unwrap();
+    assert_eq!(saves(), 1);
+
+    // last_seq 不变时心跳不写入
+    state.update(None, Some(1)).await.unwrap();
+    state.persist().await.unwrap();
+    assert_eq!(saves(), 1);
+
+    state.update(None, Some(2)).await.unwrap();
+    state.persist().await.unwrap();
+    state.persist().await.unwrap();
+    assert_eq!(saves(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_identify_limiter_paces_same_bucket() {
+    let limiter = IdentifyLimiter::new(2);
+    let start = tokio::time::Instant::now();
+
+    // 分片 0 和 1 在不同的并发桶，可以同时 Identify
+    limiter.wait(0).await;
+    limiter.wait(1).await;
+    assert_eq!(tokio::time::Instant::now(), start);
+
+    // 分片 2 与分片 0 在同一个并发桶，需要等待 5 秒
+    limiter.wait(2).await;
+    assert!(tokio::time::Instant::now() - start >= Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_session_start_limit_exhausted() {
+    let (url, _server_handle, mut received) = start_mock_server(1000).await;
+    let (api, _) = start_gateway_api(url.clone(), 0, false).await;
+
+    let mut manager = WebSocketManager::new(url, test_client_with_api(&api)).await;
+    let mut state = manager.subscribe();
+    let handle = tokio::spawn(async move {
+        manager.start().await;
+    });
+
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        state.wait_for(|s| s.status == ShardStatus::SessionLimited),
+    )
+    .await
+    .expect("未进入 SessionLimited 状态")
+    .unwrap();
+    handle.abort();
+
+    // 额度用尽时不应该连接网关
+    assert!(received.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_shard_manager_reuses_session_start_limit() {
+    use super::shard::ShardManager;
+
+    let (url, _server_handle, _received) = start_mock_server(1000).await;
+    let (api, gateway_bot_requests) = start_gateway_api(url, 1000, false).await;
+    let shutdown = tokio_util::sync::CancellationToken::new();
+    let manager = ShardManager::new(test_client_with_api(&api)).with_shutdown(shutdown.clone());
+    manager.start().await.unwrap();
+
+    let mut state = manager.subscribe(0).unwrap();
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        state.wait_for(|s| s.status == ShardStatus::Ready),
+    )
+    .await
+    .expect("未进入 Ready 状态")
+    .unwrap();
+    shutdown.cancel();
+    manager.join().await;
+
+    // Identify 使用启动时获取的额度，不再单独请求
+    assert_eq!(
+        gateway_bot_requests.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_session_start_budget_counts_down_until_reset() {
+    use super::shard::SessionStartBudget;
+    use crate::models::gateway::SessionStartLimit;
+
+    let budget = SessionStartBudget::with_limit(&SessionStartLimit {
+        total: 1000,
+        remaining: 2,
+        reset_after: 60_000,
+        max_concurrency: 1,
+    });
+    assert!(!budget.is_stale());
+    budget.acquire().unwrap();
+    budget.acquire().unwrap();
+    let err = budget.acquire().unwrap_err();
+    assert!(matches!(
+        err,
+        WebSocketError::SessionStartLimitExhausted { total: 1000, reset_after }
+            if reset_after == Duration::from_secs(60)
+    ));
+
+    tokio::time::advance(Duration::from_secs(60)).await;
+    assert!(budget.is_stale());
+}
+
+#[test]
+fn test_reconnect_policy_exponential_backoff() {
+    let policy = ReconnectPolicy::default()
+        .with_base_delay(Duration::from_millis(100))
+        .with_max_delay(Duration::from_millis(1000))
+        .with_multiplier(3.0)
+        .with_jitter(0.0);
+
+    assert_eq!(policy.delay(0), Duration::from_millis(100));
+    assert_eq!(policy.delay(1), Duration::from_millis(300));
+    assert_eq!(policy.delay(2), Duration::from_millis(900));
+    assert_eq!(policy.delay(3), Duration::from_millis(1000));
+}
+
+#[test]
+fn test_reconnect_policy_delay_never_decreases() {
+    for multiplier in [-2.0, 0.0, 0.5, f64::NAN] {
+        let timing = TimingConfig {
+            reconnect_base_delay_ms: 100,
+            reconnect_multiplier: multiplier,
+            reconnect_jitter: f64::NAN,
+            ..Default::default()
+        };
+        let from_config = ReconnectPolicy::from(&timing);
+        let built = ReconnectPolicy::default()
+            .with_base_delay(Duration::from_millis(100))
+            .with_multiplier(multiplier)
+            .with_jitter(0.0);
+        for policy in [from_config, built] {
+            let delays: Vec<_> = (0..10).map(|failures| policy.delay(failures)).collect();
+            assert_eq!(delays[0], Duration::from_millis(100));
+            assert!(
+                delays.windows(2).all(|w| w[0] <= w[1]),
+                "multiplier {multiplier}: {delays:?}"
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_reconnect_policy_gives_up() {
+    // 绑定后立即释放端口，保证连接被拒绝
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let policy = ReconnectPolicy::default()
+        .with_base_delay(Duration::from_millis(10))
+        .with_jitter(0.0)
+        .with_max_attempts(Some(2))
+        .with_give_up(GiveUp::Stop);
+
+    let mut manager = WebSocketManager::new(format!("ws://{}/", addr), test_client())
+        .await
+        .with_reconnect_policy(policy);
+
+    tokio::time::timeout(Duration::from_secs(5), manager.start())
+        .await
+        .expect("达到重连上限后应停止重连");
+}
+
+#[tokio::test]
+async fn test_refresh_gateway_url_after_failures() {
+    let (url, _server_handle, mut received) = start_mock_server(1000).await;
+    let (api, _) = start_gateway_api(url, 1000, true).await;
+
+    let dead_url = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("ws://{}/", listener.local_addr().unwrap())
+    };
+    let mut config = test_client_with_api(&api).config().clone();
+    config.timing.refresh_url_after_failures = 1;
+    let client = QQClient::new(config);
+    client.set_access_token("expired_token".into());
+
+    let policy = ReconnectPolicy::default()
+        .with_base_delay(Duration::from_millis(10))
+        .with_jitter(0.0);
+    let mut manager = WebSocketManager::new(dead_url, client.clone())
+        .await
+        .with_reconnect_policy(policy);
+    let handle = tokio::spawn(async move {
+        manager.start().await;
+    });
+
+    // 连接失败后重新获取网关地址，401 时重新鉴权
+    let event = next_session_op(&mut received).await;
+    handle.abort();
+
+    assert_eq!(event.op, u8::from(OpCode::Identify));
+    assert_eq!(client.get_access_token().as_deref(), Some("new_token"));
+    assert_eq!(event.d.unwrap()["token"], "QQBot new_token");
+}
+
+#[tokio::test]
+async fn test_shutdown_sends_close_frame() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let path = std::env::temp_dir().join(format!("qq-bot-shutdown-{}.json", std::process::id()));
+    let store = Arc::new(FileSessionStore::new(&path));
+
+    let shutdown = tokio_util::sync::CancellationToken::new();
+    let mut manager = WebSocketManager::new(url, test_client())
+        .await
+        .with_session_store(store.clone())
+        .with_shutdown(shutdown.clone());
+    let handle = tokio::spawn(async move {
+        manager.start().await;
+    });
+
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut ws_stream = accept_async(stream).await.unwrap();
+    let hello = json!({ "op": 10, "d": { "heartbeat_interval": 1000 } });
+    ws_stream
+        .send(Message::Text(hello.to_string().into()))
+        .await
+        .unwrap();
+    let _identify = ws_stream.next().await.unwrap().unwrap();
+    let ready = json!({ "op": 0, "t": "READY", "d": { "session_id": "shutdown_session" }, "s": 5 });
+    ws_stream
+        .send(Message::Text(ready.to_string().into()))
+        .await
+        .unwrap();
+
+    // 等待 Ready 处理完成后再关闭
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown.cancel();
+
+    let close = tokio::time::timeout(Duration::from_secs(2), ws_stream.next())
+        .await
+        .expect("未收到 Close 帧")
+        .unwrap()
+        .unwrap();
+    let Message::Close(Some(frame)) = close else {
+        panic!("期望 Close 帧，实际收到 {:?}", close);
+    };
+    assert_eq!(
+        frame.code,
+        tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode::Normal
+    );
+    // 回应 Close 帧
+    while ws_stream.next().await.is_some() {}
+
+    tokio::time::timeout(Duration::from_secs(2), handle)
+        .await
+        .expect("关闭后 start 未返回")
+        .unwrap();
+
+    let saved = store.load(0).await.unwrap().unwrap();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(saved.session_id.as_deref(), Some("shutdown_session"));
+    assert_eq!(saved.last_seq, Some(5));
+}
+
+#[tokio::test]
+async fn test_connection_state_published() {
+    let (url, _server_handle, _received) = start_mock_server(200).await;
+    let mut manager = WebSocketManager::new(url, test_client())
+        .await
+        .with_shard(1, 2);
+    let mut state = manager.subscribe();
+    assert_eq!(state.borrow().status, ShardStatus::Pending);
+    let handle = tokio::spawn(async move {
+        manager.start().await;
+    });
+
+    let ready = tokio::time::timeout(
+        Duration::from_secs(5),
+        state.wait_for(|s| s.status == ShardStatus::Ready),
+    )
+    .await
+    .expect("未进入 Ready 状态")
+    .unwrap()
+    .clone();
+    assert_eq!(ready.shard_id, 1);
+    assert_eq!(ready.session_id.as_deref(), Some("test_session_id"));
+    assert_eq!(ready.last_seq, Some(1));
+    assert_eq!(ready.reconnect_count, 0);
+
+    // 心跳 ACK 后记录 RTT
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        state.wait_for(|s| s.heartbeat_rtt.is_some() && s.heartbeat_rtt_avg.is_some()),
+    )
+    .await
+    .expect("未记录心跳 RTT")
+    .unwrap();
+    handle.abort();
+}
+
+#[test]
+fn test_rtt_window_rolling_average() {
+    let mut window = RttWindow::new(3);
+    assert_eq!(window.average(), None);
+
+    assert_eq!(
+        window.record(Duration::from_millis(10)),
+        Duration::from_millis(10)
+    );
+    assert_eq!(
+        window.record(Duration::from_millis(20)),
+        Duration::from_millis(15)
+    );
+    assert_eq!(
+        window.record(Duration::from_millis(30)),
+        Duration::from_millis(20)
+    );
+    // 窗口已满，丢弃最早的 10ms
+    assert_eq!(
+        window.record(Duration::from_millis(100)),
+        Duration::from_millis(50)
+    );
+
+    window.clear();
+    assert_eq!(window.average(), None);
+}
+
+/// 接受一个网关连接并发送 Hello
+async fn accept_gateway(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+    let (stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .expect("客户端未重连")
+        .unwrap();
+    let mut ws_stream = accept_async(stream).await.unwrap();
+    let hello = json!({ "op": 10, "d": { "heartbeat_interval": 30000 } });
+    ws_stream
+        .send(Message::Text(hello.to_string().into()))
+        .await
+        .unwrap();
+    ws_stream
+}
+
+/// 读取客户端发送的下一个包
+async fn next_client_event(ws_stream: &mut WebSocketStream<TcpStream>) -> QQBotEvent {
+    loop {
+        let msg = tokio::time::timeout(Duration::from_secs(5), ws_stream.next())
+            .await
+            .expect("未收到客户端消息")
+            .unwrap()
+            .unwrap();
+        if let Message::Text(text) = msg {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+/// 等待客户端的 Close 帧，回应后等待连接结束
+async fn next_client_close(ws_stream: &mut WebSocketStream<TcpStream>) -> Option<CloseFrame> {
+    let frame = loop {
+        let msg = tokio::time::timeout(Duration::from_secs(5), ws_stream.next())
+            .await
+            .expect("未收到 Close 帧");
+        match msg {
+            Some(Ok(Message::Close(frame))) => break frame,
+            Some(Ok(_)) => continue,
+            _ => panic!("连接在收到 Close 帧前断开"),
+        }
+    };
+    while ws_stream.next().await.is_some() {}
+    frame
+}
+
+/// 完成 Identify 并下发 READY，返回后客户端持有会话 `op7_session`，seq 为 1
+async fn identify_and_ready(ws_stream: &mut WebSocketStream<TcpStream>) {
+    let identify = next_client_event(ws_stream).await;
+    assert_eq!(identify.op, u8::from(OpCode::Identify));
+    let ready = json!({ "op": 0, "t": "READY", "d": { "session_id": "op7_session" }, "s": 1 });
+    ws_stream
+        .send(Message::Text(ready.to_string().into()))
+        .await
+        .unwrap();
+}
+
+/// 启动一个快速重连的管理器，返回网关监听器
+async fn start_fast_reconnect_manager() -> (TcpListener, tokio::task::JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let policy = ReconnectPolicy::default()
+        .with_base_delay(Duration::from_millis(10))
+        .with_jitter(0.0);
+    let mut manager = WebSocketManager::new(url, test_client())
+        .await
+        .with_reconnect_policy(policy);
+    let handle = tokio::spawn(async move {
+        manager.start().await;
+    });
+    (listener, handle)
+}
+
+#[tokio::test]
+async fn test_reconnect_op_resumes_session() {
+    let (listener, handle) = start_fast_reconnect_manager().await;
+    let mut ws_stream = accept_gateway(&listener).await;
+    identify_and_ready(&mut ws_stream).await;
+
+    let reconnect = json!({ "op": 7 });
+    ws_stream
+        .send(Message::Text(reconnect.to_string().into()))
+        .await
+        .unwrap();
+
+    // 不能以 1000 关闭，否则服务端会废弃会话
+    let frame = next_client_close(&mut ws_stream).await.unwrap();
+    assert_eq!(u16::from(frame.code), 4000);
+
+    let mut ws_stream = accept_gateway(&listener).await;
+    let resume = next_client_event(&mut ws_stream).await;
+    handle.abort();
+
+    assert_eq!(resume.op, u8::from(OpCode::Resume));
+    let d = resume.d.unwrap();
+    assert_eq!(d["session_id"], "op7_session");
+    assert_eq!(d["seq"], 1);
+}
+
+#[tokio::test]
+async fn test_reconnect_op_waits_base_delay() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let policy = ReconnectPolicy::default()
+        .with_base_delay(Duration::from_millis(300))
+        .with_jitter(0.0);
+    let mut manager = WebSocketManager::new(url, test_client())
+        .await
+        .with_reconnect_policy(policy);
+    let handle = tokio::spawn(async move {
+        manager.start().await;
+    });
+
+    let mut ws_stream = accept_gateway(&listener).await;
+    identify_and_ready(&mut ws_stream).await;
+    // 连续要求重连，每次都要等待基础延迟
+    for _ in 0..2 {
+        let reconnect = json!({ "op": 7 });
+        ws_stream
+            .send(Message::Text(reconnect.to_string().into()))
+            .await
+            .unwrap();
+        next_client_close(&mut ws_stream).await;
+        let closed_at = tokio::time::Instant::now();
+
+        ws_stream = accept_gateway(&listener).await;
+        assert!(closed_at.elapsed() >= Duration::from_millis(250));
+        let resume = next_client_event(&mut ws_stream).await;
+        assert_eq!(resume.op, u8::from(OpCode::Resume));
+    }
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_invalid_session_resumable() {
+    let (listener, handle) = start_fast_reconnect_manager().await;
+    let mut ws_stream = accept_gateway(&listener).await;
+    identify_and_ready(&mut ws_stream).await;
+
+    let invalid = json!({ "op": 9, "d": true });
+    ws_stream
+        .send(Message::Text(invalid.to_string().into()))
+        .await
+        .unwrap();
+    let frame = next_client_close(&mut ws_stream).await.unwrap();
+    assert_eq!(u16::from(frame.code), 4000);
+
+    let mut ws_stream = accept_gateway(&listener).await;
+    let resume = next_client_event(&mut ws_stream).await;
+    handle.abort();
+
+    assert_eq!(resume.op, u8::from(OpCode::Resume));
+    assert_eq!(resume.d.unwrap()["session_id"], "op7_session");
+}
+
+#[tokio::test]
+async fn test_invalid_session_not_resumable() {
+    let (listener, handle) = start_fast_reconnect_manager().await;
+    let mut ws_stream = accept_gateway(&listener).await;
+    identify_and_ready(&mut ws_stream).await;
+
+    let invalid = json!({ "op": 9, "d": false });
+    ws_stream
+        .send(Message::Text(invalid.to_string().into()))
+        .await
+        .unwrap();
+    next_client_close(&mut ws_stream).await;
+
+    // 会话已清空，重连后重新 Identify
+    let mut ws_stream = accept_gateway(&listener).await;
+    let identify = next_client_event(&mut ws_stream).await;
+    handle.abort();
+
+    assert_eq!(identify.op, u8::from(OpCode::Identify));
+}
+
+#[tokio::test]
+async fn test_gateway_commands_buffered_across_reconnect() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let policy = ReconnectPolicy::default()
+        .with_base_delay(Duration::from_millis(10))
+        .with_jitter(0.0);
+    let mut manager = WebSocketManager::new(url, test_client())
+        .await
+        .with_reconnect_policy(policy);
+    let commands = manager.command_sender();
+    let mut state = manager.subscribe();
+    let handle = tokio::spawn(async move {
+        manager.start().await;
+    });
+
+    let mut ws_stream = accept_gateway(&listener).await;
+    identify_and_ready(&mut ws_stream).await;
+    state
+        .wait_for(|s| s.status == ShardStatus::Ready)
+        .await
+        .unwrap();
+
+    // 心跳间隔为 30 秒，立即收到的心跳来自指令
+    commands.heartbeat().await.unwrap();
+    let heartbeat = next_client_event(&mut ws_stream).await;
+    assert_eq!(heartbeat.op, u8::from(OpCode::Heartbeat));
+
+    commands
+        .send_raw(3, Some(json!({ "status": "online" })))
+        .await
+        .unwrap();
+    let raw = next_client_event(&mut ws_stream).await;
+    assert_eq!(raw.op, 3);
+    assert_eq!(raw.d.unwrap()["status"], "online");
+
+    // 断线期间发送的指令在 Resume 完成后发出
+    drop(ws_stream);
+    state
+        .wait_for(|s| s.status != ShardStatus::Ready)
+        .await
+        .unwrap();
+    commands
+        .send_raw(3, Some(json!({ "status": "buffered" })))
+        .await
+        .unwrap();
+
+    let mut ws_stream = accept_gateway(&listener).await;
+    let resume = next_client_event(&mut ws_stream).await;
+    assert_eq!(resume.op, u8::from(OpCode::Resume));
+    let resumed = json!({ "op": 0, "t": "RESUMED", "d": {}, "s": 2 });
+    ws_stream
+        .send(Message::Text(resumed.to_string().into()))
+        .await
+        .unwrap();
+
+    let raw = next_client_event(&mut ws_stream).await;
+    handle.abort();
+    assert_eq!(raw.op, 3);
+    assert_eq!(raw.d.unwrap()["status"], "buffered");
+}
+
+#[tokio::test]
+async fn test_gateway_events_reach_dispatcher() {
+    use crate::services::dispatcher::Dispatcher;
+    use crate::test_support::{ForwardingHandler, c2c_message_event};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    let client = test_client();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let dispatcher = Dispatcher::new(client.clone(), Arc::new(ForwardingHandler(tx)));
+    let mut manager = WebSocketManager::new(url, client)
+        .await
+        .with_dispatcher(dispatcher);
+    let handle = tokio::spawn(async move {
+        manager.start().await;
+    });
+
+    let mut ws_stream = accept_gateway(&listener).await;
+    identify_and_ready(&mut ws_stream).await;
+    let mut event = c2c_message_event("1", "from gateway");
+    event.s = Some(2);
+    ws_stream
+        .send(Message::Text(serde_json::to_string(&event).unwrap().into()))
+        .await
+        .unwrap();
+
+    let received = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("事件未分发到处理器")
+        .unwrap();
+    handle.abort();
+    assert_eq!(received, "c2c:from gateway");
+}
+
+#[tokio::test]
+async fn test_gateway_mode_runs_without_listener() {
+    use crate::config::RunMode;
+    use crate::services::server::ServerBuilder;
+
+    let (url, _server_handle, _received) = start_mock_server(1000).await;
+    let (api, _) = start_gateway_api(url, 1000, false).await;
+    let config = Config {
+        mode: RunMode::Gateway,
+        // 仅网关模式不会监听，无效地址也不影响启动
+        listen_addr: "invalid address".to_string(),
+        ..test_client_with_api(&api).config().clone()
+    };
+
+    let server = ServerBuilder::new(config);
+    let handle = server.handle();
+    let run = tokio::spawn(server.run());
+
+    let mut state = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(state) = handle.connection_state(0) {
+                break state;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("分片未启动");
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        state.wait_for(|s| s.status == ShardStatus::Ready),
+    )
+    .await
+    .expect("未进入 Ready 状态")
+    .unwrap();
+
+    handle.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), run)
+        .await
+        .expect("关闭后 run 未返回")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_gateway_mode_shutdown_waits_for_shards() {
+    use crate::config::RunMode;
+    use crate::services::server::ServerBuilder;
+
+    let (url, _server_handle, _received) = start_mock_server(1000).await;
+    let (api, _) = start_gateway_api(url, 1000, false).await;
+    let config = Config {
+        mode: RunMode::Gateway,
+        ..test_client_with_api(&api).config().clone()
+    };
+    let path = std::env::temp_dir().join(format!(
+        "qq-bot-gateway-shutdown-{}.json",
+        std::process::id()
+    ));
+    let store = FileSessionStore::new(&path);
+
+    let server = ServerBuilder::new(config).with_session_store(FileSessionStore::new(&path));
+    let handle = server.handle();
+    let run = tokio::spawn(server.run());
+
+    let mut state = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(state) = handle.connection_state(0) {
+                break state;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("分片未启动");
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        state.wait_for(|s| s.status == ShardStatus::Ready),
+    )
+    .await
+    .expect("未进入 Ready 状态")
+    .unwrap();
+
+    handle.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), run)
+        .await
+        .expect("关闭后 run 未返回")
+        .unwrap()
+        .unwrap();
+
+    // run 返回时分片已经关闭并保存了会话
+    assert_eq!(state.borrow().status, ShardStatus::Closed);
+    let saved = store.load(0).await.unwrap();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(
+        saved.and_then(|s| s.session_id).as_deref(),
+        Some("test_session_id")
+    );
+}
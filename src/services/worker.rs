@@ -0,0 +1,288 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures_util::FutureExt;
+use tokio_util::task::TaskTracker;
+use tracing::error;
+
+/// 默认最多同时处理的事件数
+const DEFAULT_MAX_CONCURRENCY: usize = 64;
+
+type Job = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+tokio::task_local! {
+    static CURRENT: Arc<Slot>;
+}
+
+/// 让出当前任务在工作池中占用的名额和会话顺序
+///
+/// 之后同一会话的事件不再等待该任务，任务本身继续运行但不再计入并发数。
+/// 不在工作池任务中调用时不做任何事。
+pub(crate) fn release_current() {
+    let _ = CURRENT.try_with(|slot| slot.release());
+}
+
+/// 事件处理的并发配置
+#[derive(Debug, Clone, Copy)]
+pub struct WorkerPoolConfig {
+    /// 最多同时处理的事件数，0 表示不限制
+    pub max_concurrency: usize,
+    /// 同一会话（群、单聊用户或子频道）的事件按收到的顺序逐个处理
+    pub ordered: bool,
+}
+
+impl WorkerPoolConfig {
+    pub fn new(max_concurrency: usize) -> Self {
+        Self {
+            max_concurrency,
+            ..Default::default()
+        }
+    }
+
+    /// 设置是否按会话顺序处理
+    pub fn with_ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+}
+
+impl Default for WorkerPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            ordered: true,
+        }
+    }
+}
+
+/// 等待空闲名额的工作
+enum Ready {
+    /// 有待处理事件的会话，取其队首的事件
+    Key(String),
+    /// 不属于任何会话的事件
+    Job(Job),
+}
+
+#[derive(Default)]
+struct PoolState {
+    /// 每个会话等待处理的事件；会话有事件在处理或在 `ready` 中时才存在
+    queues: HashMap<String, VecDeque<Job>>,
+    /// 按到达顺序等待空闲名额
+    ready: VecDeque<Ready>,
+    running: usize,
+}
+
+/// 事件处理的工作池
+///
+/// 同时运行的任务数不超过 `max_concurrency`，其余事件排队等待；同一会话同时只有一个
+/// 事件在处理，不同会话之间并行。任务由分发器的 [`TaskTracker`] 跟踪。
+pub(crate) struct WorkerPool {
+    config: WorkerPoolConfig,
+    state: Mutex<PoolState>,
+    tasks: TaskTracker,
+}
+
+impl WorkerPool {
+    pub(crate) fn new(config: WorkerPoolConfig, tasks: TaskTracker) -> Self {
+        Self {
+            config,
+            state: Default::default(),
+            tasks,
+        }
+    }
+
+    /// 提交一个事件处理任务，`key` 为事件所属的会话
+    pub(crate) fn submit(
+        self: &Arc<Self>,
+        key: Option<String>,
+        job: impl Future<Output = ()> + Send + 'static,
+    ) {
+        let job: Job = Box::pin(job);
+        let mut state = self.lock();
+        match key.filter(|_| self.config.ordered) {
+            Some(key) => match state.queues.get_mut(&key) {
+                // 会话已有事件在处理或排队，排在其后
+                Some(queue) => queue.push_back(job),
+                None => {
+                    state.queues.insert(key.clone(), VecDeque::from([job]));
+                    state.ready.push_back(Ready::Key(key));
+                }
+            },
+            None => state.ready.push_back(Ready::Job(job)),
+        }
+        self.schedule(&mut state);
+    }
+
+    /// 等待处理的事件数
+    pub(crate) fn queued(&self) -> usize {
+        let state = self.lock();
+        let keyed: usize = state.queues.values().map(VecDeque::len).sum();
+        let keyless = state
+            .ready
+            .iter()
+            .filter(|r| matches!(r, Ready::Job(_)))
+            .count();
+        keyed + keyless
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 在名额允许的范围内启动等待中的任务
+    fn schedule(self: &Arc<Self>, state: &mut PoolState) {
+        let limit = self.config.max_concurrency;
+        while limit == 0 || state.running < limit {
+            let Some(ready) = state.ready.pop_front() else {
+                break;
+            };
+            let (key, job) = match ready {
+                Ready::Job(job) => (None, job),
+                Ready::Key(key) => {
+                    let Some(job) = state.queues.get_mut(&key).and_then(VecDeque::pop_front) else {
+                        continue;
+                    };
+                    (Some(key), job)
+                }
+            };
+            state.running += 1;
+            let slot = Arc::new(Slot {
+                pool: self.clone(),
+                key,
+                released: AtomicBool::new(false),
+            });
+            self.tasks.spawn(async move {
+                let job = CURRENT.scope(slot.clone(), job);
+                if AssertUnwindSafe(job).catch_unwind().await.is_err() {
+                    error!("事件处理任务 panic");
+                }
+                slot.release();
+            });
+        }
+    }
+
+    /// 任务完成或让出名额，会话还有事件时重新排队
+    fn finish(self: &Arc<Self>, key: Option<String>) {
+        let mut state = self.lock();
+        state.running -= 1;
+        if let Some(key) = key {
+            if state.queues.get(&key).is_some_and(|q| !q.is_empty()) {
+                state.ready.push_back(Ready::Key(key));
+            } else {
+                state.queues.remove(&key);
+            }
+        }
+        self.schedule(&mut state);
+    }
+}
+
+/// 运行中的任务占用的名额，完成或让出时归还一次
+struct Slot {
+    pool: Arc<WorkerPool>,
+    key: Option<String>,
+    released: AtomicBool,
+}
+
+impl Slot {
+    fn release(&self) {
+        if !self.released.swap(true, Ordering::AcqRel) {
+            self.pool.finish(self.key.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::event_client::QQEvent;
+    use crate::models::{client_error::ClientError, message::C2CMessage};
+    use crate::services::{client::QQClient, dispatcher::Dispatcher};
+    use crate::test_support::{c2c_message_from, test_client};
+
+    /// 单聊内容为 `名称:毫秒`，处理时等待对应时长，记录完成顺序和最大并发数
+    #[derive(Default)]
+    struct SlowHandler {
+        finished: Mutex<Vec<String>>,
+        running: std::sync::atomic::AtomicUsize,
+        max_running: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl QQEvent for SlowHandler {
+        async fn on_c2c_message_create(
+            &self,
+            message: C2CMessage,
+            _client: &QQClient,
+        ) -> Result<(), ClientError> {
+            use std::sync::atomic::Ordering;
+
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            let (name, ms) = message.content.split_once(':').unwrap();
+            tokio::time::sleep(Duration::from_millis(ms.parse().unwrap())).await;
+            self.finished.lock().unwrap().push(name.to_string());
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_worker_pool_orders_per_conversation() {
+        let handler = Arc::new(SlowHandler::default());
+        let dispatcher = Dispatcher::new(test_client(), handler.clone());
+
+        // 同一用户的慢消息先到，后到的快消息仍排在其后；其他用户不受影响
+        dispatcher.dispatch(c2c_message_from("alice", "1", "a1:50"));
+        dispatcher.dispatch(c2c_message_from("alice", "2", "a2:1"));
+        dispatcher.dispatch(c2c_message_from("bob", "3", "b1:10"));
+        assert_eq!(dispatcher.queued(), 1);
+        dispatcher.tasks().close();
+        dispatcher.tasks().wait().await;
+
+        assert_eq!(*handler.finished.lock().unwrap(), vec!["b1", "a1", "a2"]);
+        assert_eq!(dispatcher.queued(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_worker_pool_limits_concurrency() {
+        use std::sync::atomic::Ordering;
+
+        let handler = Arc::new(SlowHandler::default());
+        let dispatcher = Dispatcher::new(test_client(), handler.clone())
+            .with_worker_pool(WorkerPoolConfig::new(2));
+
+        for i in 0..5 {
+            let user = format!("user{}", i);
+            dispatcher.dispatch(c2c_message_from(
+                &user,
+                &i.to_string(),
+                &format!("{}:10", i),
+            ));
+        }
+        assert_eq!(dispatcher.queued(), 3);
+        dispatcher.tasks().close();
+        dispatcher.tasks().wait().await;
+
+        assert_eq!(handler.finished.lock().unwrap().len(), 5);
+        assert_eq!(handler.max_running.load(Ordering::SeqCst), 2);
+
+        // 不保持顺序时同一用户的事件也并行处理
+        let handler = Arc::new(SlowHandler::default());
+        let dispatcher = Dispatcher::new(test_client(), handler.clone())
+            .with_worker_pool(WorkerPoolConfig::new(0).with_ordered(false));
+        dispatcher.dispatch(c2c_message_from("alice", "1", "a1:50"));
+        dispatcher.dispatch(c2c_message_from("alice", "2", "a2:1"));
+        dispatcher.tasks().close();
+        dispatcher.tasks().wait().await;
+        assert_eq!(*handler.finished.lock().unwrap(), vec!["a2", "a1"]);
+    }
+}
@@ -0,0 +1,223 @@
+//! 各模块测试共用的模拟 API、客户端和事件
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+};
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+use crate::event_client::QQEvent;
+use crate::models::{
+    client_error::ClientError,
+    event::QQBotEvent,
+    message::{C2CMessage, GroupMessage},
+};
+use crate::services::client::QQClient;
+
+/// 无法连接的 API 地址，请求会立即失败
+pub(crate) const OFFLINE_API_BASE_URL: &str = "http://127.0.0.1:1";
+
+/// 请求 `api_base_url` 的配置，鉴权地址在同一个服务上
+pub(crate) fn test_config(api_base_url: &str) -> Config {
+    Config {
+        app_id: "test_app_id".to_string(),
+        client_secret: "test_secret".to_string(),
+        auth_url: Some(format!(
+            "{}/app/getAppAccessToken",
+            api_base_url.trim_end_matches('/')
+        )),
+        api_base_url: Some(api_base_url.to_string()),
+        ..Default::default()
+    }
+}
+
+/// 已设置 access token 的客户端，不会发起鉴权请求
+pub(crate) fn client_with_config(config: Config) -> QQClient {
+    let client = QQClient::new(config);
+    client.set_access_token("test_token".into());
+    client
+}
+
+/// 请求 [`OFFLINE_API_BASE_URL`] 的客户端
+pub(crate) fn test_client() -> QQClient {
+    client_with_config(test_config(OFFLINE_API_BASE_URL))
+}
+
+async fn serve(app: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{}", addr)
+}
+
+/// 记录收到的请求体，并按顺序返回预设的响应
+#[derive(Clone, Default)]
+pub(crate) struct MockApi {
+    pub(crate) requests: Arc<Mutex<Vec<Value>>>,
+    responses: Arc<Mutex<Vec<(StatusCode, Value)>>>,
+}
+
+async fn mock_messages(State(api): State<MockApi>, Json(body): Json<Value>) -> impl IntoResponse {
+    api.requests.lock().unwrap().push(body);
+    let (status, body) = {
+        let mut responses = api.responses.lock().unwrap();
+        if responses.is_empty() {
+            (StatusCode::OK, json!({ "id": "msg_id" }))
+        } else {
+            responses.remove(0)
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert("X-Tps-trace-ID", "trace-123".parse().unwrap());
+    (status, headers, Json(body))
+}
+
+/// 模拟发送群消息的接口，响应用完后返回成功
+pub(crate) async fn start_mock_api(responses: Vec<(StatusCode, Value)>) -> (QQClient, MockApi) {
+    let api = MockApi {
+        responses: Arc::new(Mutex::new(responses)),
+        ..Default::default()
+    };
+    let app = Router::new()
+        .route("/v2/groups/{group_openid}/messages", post(mock_messages))
+        .with_state(api.clone());
+    let url = serve(app).await;
+
+    (client_with_config(test_config(&format!("{}/", url))), api)
+}
+
+/// 模拟网关相关的接口，返回 API 地址和 `GET /gateway/bot` 的请求次数
+///
+/// `GET /gateway/bot` 返回指定的 Session 剩余创建次数；`unauthorized_once` 为 true 时
+/// 第一次 `GET /gateway` 返回 401，重新鉴权后才返回网关地址。
+pub(crate) async fn start_gateway_api(
+    ws_url: String,
+    remaining: u32,
+    unauthorized_once: bool,
+) -> (String, Arc<AtomicUsize>) {
+    let gateway_bot_requests = Arc::new(AtomicUsize::new(0));
+    let counter = gateway_bot_requests.clone();
+    let gateway = json!({ "url": ws_url });
+    let gateway_bot = json!({
+        "url": ws_url,
+        "shards": 1,
+        "session_start_limit": {
+            "total": 1000,
+            "remaining": remaining,
+            "reset_after": 60_000,
+            "max_concurrency": 1
+        }
+    });
+    let unauthorized = Arc::new(AtomicBool::new(unauthorized_once));
+    let app = Router::new()
+        .route(
+            "/gateway/bot",
+            get(move || async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Json(gateway_bot)
+            }),
+        )
+        .route(
+            "/gateway",
+            get(move || async move {
+                if unauthorized.swap(false, Ordering::SeqCst) {
+                    let body = json!({ "code": 11244, "message": "token not exist" });
+                    (StatusCode::UNAUTHORIZED, Json(body))
+                } else {
+                    (StatusCode::OK, Json(gateway))
+                }
+            }),
+        )
+        .route(
+            "/app/getAppAccessToken",
+            post(|| async { Json(json!({ "access_token": "new_token", "expires_in": "7200" })) }),
+        );
+
+    (serve(app).await, gateway_bot_requests)
+}
+
+/// 把收到的消息以 `group:内容` / `c2c:内容` 的形式转发出去的事件处理器
+pub(crate) struct ForwardingHandler(pub mpsc::UnboundedSender<String>);
+
+#[async_trait]
+impl QQEvent for ForwardingHandler {
+    async fn on_group_at_message_create(
+        &self,
+        message: GroupMessage,
+        _client: &QQClient,
+    ) -> Result<(), ClientError> {
+        let _ = self.0.send(format!("group:{}", message.content));
+        Ok(())
+    }
+
+    async fn on_c2c_message_create(
+        &self,
+        message: C2CMessage,
+        _client: &QQClient,
+    ) -> Result<(), ClientError> {
+        let _ = self.0.send(format!("c2c:{}", message.content));
+        Ok(())
+    }
+}
+
+pub(crate) fn group_message_event(id: &str, content: &str) -> QQBotEvent {
+    QQBotEvent {
+        id: Some(format!("GROUP_AT_MESSAGE_CREATE:{id}")),
+        op: 0,
+        t: Some("GROUP_AT_MESSAGE_CREATE".to_string()),
+        d: Some(json!({
+            "author": { "id": "member", "member_openid": "member_openid", "union_openid": "union" },
+            "content": content,
+            "group_id": "group",
+            "group_openid": "group_openid",
+            "id": id,
+            "message_scene": { "source": "default" },
+            "message_type": 0,
+            "timestamp": "2025-01-01T00:00:00+08:00"
+        })),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn c2c_message_event(id: &str, content: &str) -> QQBotEvent {
+    QQBotEvent {
+        id: Some(format!("C2C_MESSAGE_CREATE:{id}")),
+        op: 0,
+        t: Some("C2C_MESSAGE_CREATE".to_string()),
+        d: Some(json!({
+            "author": { "id": "user", "union_openid": "union", "user_openid": "user_openid" },
+            "content": content,
+            "id": id,
+            "message_scene": { "source": "default" },
+            "message_type": 0,
+            "timestamp": "2025-01-01T00:00:00+08:00"
+        })),
+        ..Default::default()
+    }
+}
+
+/// 群成员 `member` 发送的群消息
+pub(crate) fn group_message_from(member: &str, id: &str, content: &str) -> QQBotEvent {
+    let mut event = group_message_event(id, content);
+    event.d.as_mut().unwrap()["author"]["member_openid"] = json!(member);
+    event
+}
+
+/// 用户 `user` 发送的单聊消息
+pub(crate) fn c2c_message_from(user: &str, id: &str, content: &str) -> QQBotEvent {
+    let mut event = c2c_message_event(id, content);
+    event.d.as_mut().unwrap()["author"]["user_openid"] = json!(user);
+    event
+}